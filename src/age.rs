@@ -0,0 +1,107 @@
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use walkdir::WalkDir;
+
+use crate::pyvenv::site_packages;
+use crate::scan::SKIPPED_DIRS;
+use crate::Environment;
+
+/// How far into a project to look for recently touched source files
+const PROJECT_DEPTH: usize = 3;
+
+/// parses a duration like `30d`, `2w` or `12h`
+pub fn parse_age(value: &str) -> Result<Duration, String> {
+    humantime::parse_duration(value).map_err(|err| err.to_string())
+}
+
+/// formats a time as a date, for showing to humans
+pub fn format_date(time: SystemTime) -> String {
+    humantime::format_rfc3339_seconds(time)
+        .to_string()
+        .chars()
+        .take(10)
+        .collect()
+}
+
+/// when a path was last changed, and optionally read, without following symlinks. A symlink's
+/// access time changes whenever anything resolves it, so it's never counted.
+fn touched(path: &Path, include_atime: bool) -> Option<SystemTime> {
+    let metadata = path.symlink_metadata().ok()?;
+    let accessed = match include_atime && !metadata.file_type().is_symlink() {
+        true => metadata.accessed().ok(),
+        false => None,
+    };
+    [metadata.modified().ok(), accessed]
+        .into_iter()
+        .flatten()
+        .max()
+}
+
+/// the newest activity in an environment.
+///
+/// Access times are only trusted on files that scanning never reads: the interpreter, unless it's
+/// a symlink, the activation scripts, including the `postactivate` hook virtualenvwrapper's
+/// `workon` runs, and the `.pth` files in site-packages, which `site.py` reads every time the
+/// interpreter starts.
+/// Directories and `pyvenv.cfg` get read by every scan, so only their modification time counts.
+fn environment_last_touched(path: &Path) -> Option<SystemTime> {
+    let site_packages = site_packages(path);
+    let pth_files = site_packages
+        .iter()
+        .filter_map(|dir| std::fs::read_dir(dir).ok())
+        .flat_map(|entries| entries.filter_map(|entry| entry.ok()))
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "pth"))
+        .collect::<Vec<_>>();
+
+    let modified_only = [
+        path.to_path_buf(),
+        path.join("pyvenv.cfg"),
+        path.join("conda-meta").join("history"),
+    ]
+    .into_iter()
+    .chain(site_packages)
+    .filter_map(|path| touched(&path, false));
+    let accessed = [
+        path.join("bin").join("python"),
+        path.join("Scripts").join("python.exe"),
+        path.join("bin").join("activate"),
+        path.join("bin").join("postactivate"),
+        path.join("Scripts").join("activate.bat"),
+    ]
+    .into_iter()
+    .chain(pth_files)
+    .filter_map(|path| touched(&path, true));
+    modified_only.chain(accessed).max()
+}
+
+/// the newest modification of the project's files, leaving out its environments and caches
+fn project_last_touched(project: &Path, environment: &Path) -> Option<SystemTime> {
+    WalkDir::new(project)
+        .max_depth(PROJECT_DEPTH)
+        .into_iter()
+        .filter_entry(|entry| {
+            entry.depth() == 0
+                || !(entry.path().starts_with(environment)
+                    || entry.file_type().is_dir()
+                        && (entry.file_name().to_string_lossy().starts_with('.')
+                            || SKIPPED_DIRS.iter().any(|name| entry.file_name() == *name)))
+        })
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.file_type().is_file())
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .max()
+}
+
+/// the last time anything suggests the environment or its project was used
+pub fn last_used(environment: &Environment) -> Option<SystemTime> {
+    let project = environment
+        .project
+        .as_ref()
+        .and_then(|project| project_last_touched(project, &environment.path));
+    environment_last_touched(&environment.path)
+        .into_iter()
+        .chain(project)
+        .max()
+}
@@ -0,0 +1,251 @@
+//! Defaults for the command line options, read from `~/.config/python-sweep/config.toml` and then
+//! the nearest `.python-sweep.toml` at or above the directory being scanned. Each layer overrides
+//! the one before it, and options given on the command line override them all. Config files
+//! further down, in the projects being walked, aren't read: the options are fixed before the walk
+//! starts.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::age::parse_age;
+use crate::detectors::{builtin_detectors, DETECTOR_NAMES};
+use crate::keep::Excludes;
+use crate::markers::DEFAULT_MARKERS;
+use crate::scan::{Scanner, SortOrder};
+use crate::size::{parse_size, SizeMode};
+use crate::{env_var, Errors};
+
+/// The name of the per-directory config file
+pub const LOCAL_CONFIG: &str = ".python-sweep.toml";
+
+/// Everything that can be set in a config file. Unset options fall through to the next layer.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    /// Paths to search when none is given on the command line
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub roots: Vec<PathBuf>,
+    /// Paths not to walk into, see [Excludes]. Unlike everything else, these add up across the
+    /// config files and the command line, so nothing can un-exclude a path.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+    pub max_depth: Option<usize>,
+    pub deep: Option<bool>,
+    /// Skip what `.gitignore`, `.ignore` and `.sweepignore` files ignore, apart from environments
+    pub respect_ignores: Option<bool>,
+    /// Don't walk into other filesystems mounted inside a root
+    pub one_file_system: Option<bool>,
+    /// Walk into pseudo filesystems like `/proc` and overlay mounts too
+    pub pseudo_filesystems: Option<bool>,
+    /// Look for environments whose project has gone in tools' own directories, instead of walking
+    pub orphans: Option<bool>,
+    /// Leave out the environment a tool like poetry is using for each project
+    pub keep_active: Option<bool>,
+    pub markers: Option<Vec<String>>,
+    pub detectors: Option<Vec<String>>,
+    pub skip_detectors: Option<Vec<String>>,
+    pub jobs: Option<usize>,
+    pub sort: Option<SortOrder>,
+    pub size_mode: Option<SizeMode>,
+    /// An age like `30d`
+    pub older_than: Option<String>,
+    /// A size like `200MB`
+    pub min_size: Option<String>,
+    pub top: Option<usize>,
+    /// Delete what's found, still asking first unless `--non-interactive` is given
+    pub delete: Option<bool>,
+    /// Move what's found to the trash, still asking first unless `--non-interactive` is given
+    pub trash: Option<bool>,
+}
+
+/// expands a leading `~`, and makes relative paths relative to the config file they came from
+fn resolve_path(path: &Path, base: &Path) -> PathBuf {
+    if let Ok(rest) = path.strip_prefix("~") {
+        if let Some(home) = std::env::var_os("HOME") {
+            return PathBuf::from(home).join(rest);
+        }
+    }
+    base.join(path)
+}
+
+impl Config {
+    /// The built in defaults, the bottom layer of every configuration
+    pub fn defaults() -> Self {
+        Self {
+            roots: vec![],
+            exclude: vec![],
+            max_depth: None,
+            deep: Some(false),
+            respect_ignores: Some(false),
+            one_file_system: Some(false),
+            pseudo_filesystems: Some(false),
+            orphans: Some(false),
+            keep_active: Some(false),
+            markers: Some(DEFAULT_MARKERS.iter().map(|m| m.to_string()).collect()),
+            detectors: Some(DETECTOR_NAMES.iter().map(|d| d.to_string()).collect()),
+            skip_detectors: Some(vec![]),
+            jobs: Some(1),
+            sort: None,
+            size_mode: Some(SizeMode::default()),
+            older_than: None,
+            min_size: None,
+            top: None,
+            delete: Some(false),
+            trash: Some(false),
+        }
+    }
+
+    /// `$XDG_CONFIG_HOME/python-sweep/config.toml`, or `~/.config/python-sweep/config.toml`
+    pub fn global_path() -> Option<PathBuf> {
+        let config_home = match env_var("XDG_CONFIG_HOME") {
+            Some(config_home) => config_home,
+            None => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+        };
+        Some(config_home.join("python-sweep").join("config.toml"))
+    }
+
+    /// the nearest [LOCAL_CONFIG] in `start` or one of its parents
+    pub fn local_path(start: &Path) -> Option<PathBuf> {
+        std::path::absolute(start)
+            .ok()?
+            .ancestors()
+            .map(|dir| dir.join(LOCAL_CONFIG))
+            .find(|path| path.is_file())
+    }
+
+    /// reads a config file, checking the values that are only parsed when they're used
+    pub fn load(path: &Path) -> Result<Self, Errors> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|err| Errors::io("read config", path, err))?;
+        let invalid = |message: String| {
+            Errors::Other(format!("Invalid config {}: {}", path.display(), message))
+        };
+        let mut config: Self = toml::from_str(&contents).map_err(|err| invalid(err.to_string()))?;
+        if let Some(older_than) = &config.older_than {
+            parse_age(older_than).map_err(|err| invalid(format!("older-than: {}", err)))?;
+        }
+        Excludes::new(&config.exclude).map_err(|err| invalid(err.to_string()))?;
+        if let Some(min_size) = &config.min_size {
+            parse_size(min_size).map_err(|err| invalid(format!("min-size: {}", err)))?;
+        }
+        for name in config
+            .detectors
+            .iter()
+            .chain(config.skip_detectors.iter())
+            .flatten()
+        {
+            if !DETECTOR_NAMES.contains(&name.as_str()) {
+                return Err(invalid(format!(
+                    "unknown detector {:?}, expected one of {}",
+                    name,
+                    DETECTOR_NAMES.join(", ")
+                )));
+            }
+        }
+        let base = path.parent().unwrap_or(Path::new("."));
+        config.roots = config
+            .roots
+            .iter()
+            .map(|root| resolve_path(root, base))
+            .collect();
+        Ok(config)
+    }
+
+    /// the defaults, overridden by the global config and then the local config for `start`.
+    /// Returns the files that were read too.
+    pub fn discover(start: &Path) -> Result<(Self, Vec<PathBuf>), Errors> {
+        let mut config = Self::defaults();
+        let mut sources = vec![];
+        let global = Self::global_path().filter(|path| path.is_file());
+        for path in global.into_iter().chain(Self::local_path(start)) {
+            config = config.merge(Self::load(&path)?);
+            sources.push(path);
+        }
+        Ok((config, sources))
+    }
+
+    /// this config, with anything set in `over` replacing it
+    pub fn merge(self, over: Self) -> Self {
+        Self {
+            roots: match over.roots.is_empty() {
+                true => self.roots,
+                false => over.roots,
+            },
+            exclude: self.exclude.into_iter().chain(over.exclude).collect(),
+            max_depth: over.max_depth.or(self.max_depth),
+            deep: over.deep.or(self.deep),
+            respect_ignores: over.respect_ignores.or(self.respect_ignores),
+            one_file_system: over.one_file_system.or(self.one_file_system),
+            pseudo_filesystems: over.pseudo_filesystems.or(self.pseudo_filesystems),
+            orphans: over.orphans.or(self.orphans),
+            keep_active: over.keep_active.or(self.keep_active),
+            markers: over.markers.or(self.markers),
+            detectors: over.detectors.or(self.detectors),
+            skip_detectors: over.skip_detectors.or(self.skip_detectors),
+            jobs: over.jobs.or(self.jobs),
+            sort: over.sort.or(self.sort),
+            size_mode: over.size_mode.or(self.size_mode),
+            older_than: over.older_than.or(self.older_than),
+            min_size: over.min_size.or(self.min_size),
+            top: over.top.or(self.top),
+            delete: over.delete.or(self.delete),
+            trash: over.trash.or(self.trash),
+        }
+    }
+
+    pub fn older_than(&self) -> Option<Duration> {
+        self.older_than
+            .as_deref()
+            .and_then(|older_than| parse_age(older_than).ok())
+    }
+
+    pub fn min_size(&self) -> Option<u64> {
+        self.min_size
+            .as_deref()
+            .and_then(|min_size| parse_size(min_size).ok())
+    }
+
+    /// builds a scanner with these options
+    pub fn scanner(&self, debug: bool) -> Result<Scanner, Errors> {
+        let detectors = builtin_detectors()
+            .into_iter()
+            .filter(|detector| {
+                self.detectors
+                    .as_ref()
+                    .is_none_or(|names| names.iter().any(|name| name == detector.name()))
+                    && !self
+                        .skip_detectors
+                        .iter()
+                        .flatten()
+                        .any(|name| name == detector.name())
+            })
+            .collect::<Vec<_>>();
+
+        let mut scanner = Scanner::new()
+            .max_depth(self.max_depth)
+            .deep(self.deep.unwrap_or_default())
+            .respect_ignores(self.respect_ignores.unwrap_or_default())
+            .one_file_system(self.one_file_system.unwrap_or_default())
+            .pseudo_filesystems(self.pseudo_filesystems.unwrap_or_default())
+            .orphans(self.orphans.unwrap_or_default())
+            .keep_active(self.keep_active.unwrap_or_default())
+            .debug(debug)
+            .detectors(detectors)
+            .jobs(self.jobs.unwrap_or(1))
+            .sort(self.sort)
+            .size_mode(self.size_mode.unwrap_or_default())
+            .older_than(self.older_than())
+            .min_size(self.min_size())
+            .top(self.top)
+            .excludes(Excludes::new(&self.exclude)?);
+        if let Some(markers) = &self.markers {
+            scanner = scanner.markers(markers.clone());
+        }
+        for root in self.roots.iter() {
+            scanner = scanner.root(root);
+        }
+        Ok(scanner)
+    }
+}
@@ -0,0 +1,21 @@
+use crate::size::get_size_on_disk;
+use crate::{safety, Environment, Errors};
+
+/// permanently deletes an environment, returning the number of bytes we expect that freed.
+/// It's checked with [safety::validate] first.
+pub fn delete_environment(environment: &Environment) -> Result<u64, Errors> {
+    safety::validate(environment)?;
+    std::fs::remove_dir_all(&environment.path).map_err(|err| {
+        // remove_dir_all stops at the first thing it can't remove, so work out if it got anywhere
+        let partial = environment.path.exists()
+            && get_size_on_disk(&environment.path) < environment.usage.apparent;
+        match partial {
+            true => Errors::PartiallyDeleted {
+                path: environment.path.clone(),
+                source: err,
+            },
+            false => Errors::io("delete", &environment.path, err),
+        }
+    })?;
+    Ok(environment.usage.freed)
+}
@@ -0,0 +1,549 @@
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::pipenv;
+use crate::poetry;
+use crate::pyvenv::{self, PyvenvCfg};
+use crate::size::DiskUsage;
+use crate::virtualenvwrapper;
+use crate::Errors;
+
+/// An environment that a [Detector] found
+#[derive(Debug, Clone)]
+pub struct Environment {
+    pub path: PathBuf,
+    /// Size in bytes in the scanner's [crate::size::SizeMode], filled in by the scanner after detection
+    pub size: u64,
+    /// All the ways of measuring it, filled in alongside `size`
+    pub usage: DiskUsage,
+    /// The name of the detector that found it
+    pub kind: &'static str,
+    /// The project directory it belongs to, if we know it
+    pub project: Option<PathBuf>,
+    /// The scan root it was found under, filled in by the scanner
+    pub root: Option<PathBuf>,
+    /// The project marker file that led us to it, if any
+    pub marker: Option<String>,
+    pub cfg: Option<PyvenvCfg>,
+    /// The last time it or its project looked like it was used, filled in by the scanner
+    pub last_used: Option<SystemTime>,
+    /// Who's using it right now, if the scanner was asked to check
+    pub in_use: Option<String>,
+    /// The keep marker or pyproject.toml that says to leave it alone, filled in by the scanner
+    pub kept_by: Option<PathBuf>,
+    /// Its project doesn't exist any more
+    pub orphaned: bool,
+    /// Whether it's the one its tool uses for the project, for tools that can have several
+    pub active: Option<bool>,
+}
+
+impl Environment {
+    pub fn new(path: PathBuf, kind: &'static str) -> Self {
+        let cfg = PyvenvCfg::from_dir(&path).ok();
+        Self {
+            path,
+            size: 0,
+            usage: DiskUsage::default(),
+            kind,
+            project: None,
+            root: None,
+            marker: None,
+            cfg,
+            last_used: None,
+            in_use: None,
+            kept_by: None,
+            orphaned: false,
+            active: None,
+        }
+    }
+
+    pub fn with_project(mut self, project: &Path) -> Self {
+        self.project = Some(project.to_path_buf());
+        self
+    }
+}
+
+/// Finds environments, either belonging to a project directory or by looking at a walked path.
+///
+/// Both methods default to finding nothing, so an implementation only needs to provide the one
+/// that makes sense for the layout it understands.
+pub trait Detector {
+    /// The name used to select the detector on the command line
+    fn name(&self) -> &'static str;
+
+    /// Find environments belonging to the project in `project`, which contains a marker file
+    fn detect_project(&self, _project: &Path) -> Result<Vec<Environment>, Errors> {
+        Ok(vec![])
+    }
+
+    /// Check if a walked directory entry is itself an environment
+    fn detect_entry(&self, _entry: &walkdir::DirEntry) -> Result<Vec<Environment>, Errors> {
+        Ok(vec![])
+    }
+
+    /// Find environments in the tool's own storage whose project has been deleted
+    fn detect_orphans(&self) -> Result<Vec<Environment>, Errors> {
+        Ok(vec![])
+    }
+
+    /// Whether the tool can have several environments for a project and uses one of them, so
+    /// its environments should have [Environment::active] set when it's known
+    fn tracks_active(&self) -> bool {
+        false
+    }
+}
+
+/// How long to wait for a tool like poetry to tell us where an environment is
+pub const TOOL_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The names of all the built-in detectors, in the order they're tried
+pub const DETECTOR_NAMES: &[&str] = &[
+    "virtualenvwrapper",
+    "pyvenv",
+    "conda",
+    "uv",
+    "pdm",
+    "poetry",
+    "pipenv",
+    "hatch",
+    "tox",
+    "nox",
+    "in-project",
+];
+
+/// all the built-in detectors, in the order they're tried
+pub fn builtin_detectors() -> Vec<Box<dyn Detector>> {
+    vec![
+        // before pyvenv, so walking into `$WORKON_HOME` ties environments to their projects
+        Box::new(VirtualenvwrapperDetector),
+        Box::new(PyvenvDetector),
+        Box::new(CondaDetector),
+        Box::new(UvDetector),
+        Box::new(PdmDetector),
+        Box::new(PoetryDetector),
+        Box::new(PipenvDetector),
+        Box::new(HatchDetector),
+        Box::new(ToolEnvsDetector {
+            name: "tox",
+            config_files: &["tox.ini"],
+            pyproject_table: "[tool.tox",
+            env_dir: ".tox",
+        }),
+        Box::new(ToolEnvsDetector {
+            name: "nox",
+            config_files: &["noxfile.py"],
+            pyproject_table: "",
+            env_dir: ".nox",
+        }),
+        Box::new(InProjectDetector),
+    ]
+}
+
+/// checks if the project uses a tool, either by one of its own files or a table in pyproject.toml
+fn project_uses(project: &Path, files: &[&str], pyproject_table: &str) -> bool {
+    if files.iter().any(|file| project.join(file).exists()) {
+        return true;
+    }
+    if pyproject_table.is_empty() {
+        return false;
+    }
+    std::fs::read_to_string(project.join("pyproject.toml"))
+        .map(|contents| contents.contains(pyproject_table))
+        .unwrap_or(false)
+}
+
+/// reads all of a child's pipe on another thread, so a chatty child can't fill it up and block
+fn read_pipe<R: Read + Send + 'static>(pipe: Option<R>) -> thread::JoinHandle<String> {
+    thread::spawn(move || {
+        let mut output = String::new();
+        if let Some(mut pipe) = pipe {
+            let _ = pipe.read_to_string(&mut output);
+        }
+        output
+    })
+}
+
+/// runs a tool in the project directory and returns its stdout, if the tool is installed and had
+/// something to say. It's killed if it takes longer than [TOOL_TIMEOUT].
+fn run_tool(program: &str, args: &[&str], project: &Path) -> Result<Option<String>, Errors> {
+    if which::which(program).is_err() {
+        return Ok(None);
+    }
+    let failed = |message: String| Errors::Subprocess {
+        program: program.to_string(),
+        message,
+    };
+    let mut child = Command::new(program)
+        .args(args)
+        .current_dir(project)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|err| failed(err.to_string()))?;
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+    let started = Instant::now();
+    let status = loop {
+        match child.try_wait().map_err(|err| failed(err.to_string()))? {
+            Some(status) => break status,
+            None if started.elapsed() > TOOL_TIMEOUT => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return Err(failed(format!(
+                    "timed out after {}",
+                    humantime::format_duration(TOOL_TIMEOUT)
+                )));
+            }
+            None => thread::sleep(Duration::from_millis(10)),
+        }
+    };
+    let stdout = stdout.join().unwrap_or_default();
+    if !status.success() {
+        let stderr = stderr.join().unwrap_or_default();
+        // `poetry env info --path` exits like this when there's no environment yet
+        if stderr.trim().is_empty() && stdout.trim().is_empty() {
+            return Ok(None);
+        }
+        return Err(failed(format!("{}: {}", status, stderr.trim())));
+    }
+    Ok(Some(stdout))
+}
+
+/// turns paths printed by a tool into environments, skipping anything that doesn't exist
+fn existing_paths<I: IntoIterator<Item = PathBuf>>(
+    paths: I,
+    kind: &'static str,
+    project: &Path,
+) -> Vec<Environment> {
+    paths
+        .into_iter()
+        .filter(|path| !path.as_os_str().is_empty() && path.exists())
+        .map(|path| Environment::new(path, kind).with_project(project))
+        .collect()
+}
+
+/// turns a tool's environments whose project has gone, with where the project was, into
+/// orphaned environments
+fn orphaned(found: Vec<(PathBuf, PathBuf)>, kind: &'static str) -> Vec<Environment> {
+    found
+        .into_iter()
+        .map(|(path, project)| {
+            let mut environment = Environment::new(path, kind).with_project(&project);
+            environment.orphaned = true;
+            environment
+        })
+        .collect()
+}
+
+/// Any directory with a `pyvenv.cfg` and an interpreter
+pub struct PyvenvDetector;
+
+impl Detector for PyvenvDetector {
+    fn name(&self) -> &'static str {
+        "pyvenv"
+    }
+
+    fn detect_entry(&self, entry: &walkdir::DirEntry) -> Result<Vec<Environment>, Errors> {
+        if entry.file_type().is_dir() && pyvenv::is_virtualenv(entry.path()) {
+            Ok(vec![Environment::new(
+                entry.path().to_path_buf(),
+                self.name(),
+            )])
+        } else {
+            Ok(vec![])
+        }
+    }
+}
+
+/// Conda environments, which have a `conda-meta` directory instead of a `pyvenv.cfg`
+pub struct CondaDetector;
+
+impl Detector for CondaDetector {
+    fn name(&self) -> &'static str {
+        "conda"
+    }
+
+    fn detect_entry(&self, entry: &walkdir::DirEntry) -> Result<Vec<Environment>, Errors> {
+        let path = entry.path();
+        if !entry.file_type().is_dir() || !path.join("conda-meta").is_dir() {
+            return Ok(vec![]);
+        }
+        // the base install also has conda-meta, but deleting it would take conda with it
+        if path.join("condabin").is_dir() {
+            return Ok(vec![]);
+        }
+        Ok(vec![Environment::new(path.to_path_buf(), self.name())])
+    }
+}
+
+/// uv puts the project environment in `.venv` unless `UV_PROJECT_ENVIRONMENT` says otherwise
+pub struct UvDetector;
+
+impl Detector for UvDetector {
+    fn name(&self) -> &'static str {
+        "uv"
+    }
+
+    fn detect_project(&self, project: &Path) -> Result<Vec<Environment>, Errors> {
+        if !project_uses(project, &["uv.lock"], "[tool.uv") {
+            return Ok(vec![]);
+        }
+        let venv = match std::env::var_os("UV_PROJECT_ENVIRONMENT") {
+            Some(env_path) => project.join(env_path),
+            None => project.join(".venv"),
+        };
+        Ok(existing_paths([venv], self.name(), project))
+    }
+}
+
+/// pdm's PEP 582 `__pypackages__` directory and whatever `pdm venv list` knows about
+pub struct PdmDetector;
+
+impl Detector for PdmDetector {
+    fn name(&self) -> &'static str {
+        "pdm"
+    }
+
+    fn detect_project(&self, project: &Path) -> Result<Vec<Environment>, Errors> {
+        if !project_uses(project, &["pdm.lock", ".pdm-python"], "[tool.pdm") {
+            return Ok(vec![]);
+        }
+        let mut paths = vec![project.join("__pypackages__")];
+        // lines look like "*  in-project: /path/to/project/.venv"
+        if let Some(output) = run_tool("pdm", &["venv", "list"], project)? {
+            paths.extend(
+                output
+                    .lines()
+                    .filter_map(|line| line.split_once(": "))
+                    .map(|(_, path)| PathBuf::from(path.trim())),
+            );
+        }
+        Ok(existing_paths(paths, self.name(), project))
+    }
+}
+
+/// Asks poetry where it put the environment, which is usually outside the project
+pub struct PoetryDetector;
+
+impl Detector for PoetryDetector {
+    fn name(&self) -> &'static str {
+        "poetry"
+    }
+
+    fn detect_project(&self, project: &Path) -> Result<Vec<Environment>, Errors> {
+        if !project_uses(project, &["poetry.lock"], "[tool.poetry") {
+            return Ok(vec![]);
+        }
+        let mut located = poetry::locate(project);
+        let mut problem = None;
+        if !located.known {
+            // only poetry knows which one it's using
+            let directory = project.display().to_string();
+            match run_tool(
+                "poetry",
+                &["env", "info", "--path", "--directory", &directory],
+                project,
+            ) {
+                Ok(Some(output)) => {
+                    let active = PathBuf::from(output.trim());
+                    if !located.environments.contains(&active) {
+                        located.environments.push(active.clone());
+                    }
+                    located.active = Some(active);
+                    located.known = true;
+                }
+                Ok(None) => {}
+                Err(err) if located.environments.is_empty() => return Err(err),
+                // we still know what the environments are, just not which is active
+                Err(err) => problem = Some(err),
+            }
+        }
+        let found = existing_paths(located.environments, self.name(), project)
+            .into_iter()
+            .map(|mut environment| {
+                environment.active = located
+                    .known
+                    .then(|| located.active.as_ref() == Some(&environment.path));
+                environment
+            })
+            .collect();
+        match problem {
+            Some(err) => Err(Errors::Incomplete {
+                found,
+                source: Box::new(err),
+            }),
+            None => Ok(found),
+        }
+    }
+
+    fn tracks_active(&self) -> bool {
+        true
+    }
+
+    fn detect_orphans(&self) -> Result<Vec<Environment>, Errors> {
+        Ok(orphaned(poetry::orphaned_environments(), self.name()))
+    }
+}
+
+/// Works out where pipenv put the environment for a Pipfile, asking pipenv if that doesn't find it
+pub struct PipenvDetector;
+
+impl Detector for PipenvDetector {
+    fn name(&self) -> &'static str {
+        "pipenv"
+    }
+
+    fn detect_project(&self, project: &Path) -> Result<Vec<Environment>, Errors> {
+        if !project.join("Pipfile").exists() {
+            return Ok(vec![]);
+        }
+        let environments = pipenv::locate(project);
+        if !environments.is_empty() {
+            return Ok(existing_paths(environments, self.name(), project));
+        }
+        match run_tool("pipenv", &["--venv"], project) {
+            Ok(Some(output)) => Ok(existing_paths(
+                [PathBuf::from(output.trim())],
+                self.name(),
+                project,
+            )),
+            Ok(None) => Ok(vec![]),
+            Err(Errors::Subprocess { message, .. })
+                if message.contains("No virtualenv has been created") =>
+            {
+                Ok(vec![])
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn detect_orphans(&self) -> Result<Vec<Environment>, Errors> {
+        Ok(orphaned(pipenv::orphaned_environments(), self.name()))
+    }
+}
+
+/// Finds the environments in `$WORKON_HOME` whose `.project` file points at a project, and the
+/// ones whose project has gone
+pub struct VirtualenvwrapperDetector;
+
+impl Detector for VirtualenvwrapperDetector {
+    fn name(&self) -> &'static str {
+        "virtualenvwrapper"
+    }
+
+    fn detect_project(&self, project: &Path) -> Result<Vec<Environment>, Errors> {
+        // pipenv's environments have `.project` files too, and may share `$WORKON_HOME`
+        if project.join("Pipfile").exists() {
+            return Ok(vec![]);
+        }
+        Ok(existing_paths(
+            virtualenvwrapper::locate(project),
+            self.name(),
+            project,
+        ))
+    }
+
+    fn detect_entry(&self, entry: &walkdir::DirEntry) -> Result<Vec<Environment>, Errors> {
+        let path = entry.path();
+        if !entry.file_type().is_dir() || !path.join(".project").is_file() {
+            return Ok(vec![]);
+        }
+        if path.parent() != virtualenvwrapper::workon_home().as_deref()
+            || !pyvenv::is_virtualenv(path)
+        {
+            return Ok(vec![]);
+        }
+        let Some(project) = pyvenv::project_file(path) else {
+            return Ok(vec![]);
+        };
+        let mut environment =
+            Environment::new(path.to_path_buf(), self.name()).with_project(&project);
+        environment.orphaned = !project.exists();
+        Ok(vec![environment])
+    }
+
+    fn detect_orphans(&self) -> Result<Vec<Environment>, Errors> {
+        Ok(orphaned(
+            virtualenvwrapper::orphaned_environments(),
+            self.name(),
+        ))
+    }
+}
+
+/// Asks hatch where the default environment lives
+pub struct HatchDetector;
+
+impl Detector for HatchDetector {
+    fn name(&self) -> &'static str {
+        "hatch"
+    }
+
+    fn detect_project(&self, project: &Path) -> Result<Vec<Environment>, Errors> {
+        if !project_uses(project, &["hatch.toml"], "[tool.hatch") {
+            return Ok(vec![]);
+        }
+        match run_tool("hatch", &["env", "find"], project)? {
+            Some(output) => Ok(existing_paths(
+                output.lines().map(|line| PathBuf::from(line.trim())),
+                self.name(),
+                project,
+            )),
+            None => Ok(vec![]),
+        }
+    }
+}
+
+/// Tools like tox and nox that keep one environment per session in a directory in the project
+pub struct ToolEnvsDetector {
+    pub name: &'static str,
+    /// Files in the project that mean the tool is in use
+    pub config_files: &'static [&'static str],
+    /// The pyproject.toml table that means the tool is in use, empty if it doesn't have one
+    pub pyproject_table: &'static str,
+    /// The directory in the project holding the environments
+    pub env_dir: &'static str,
+}
+
+impl Detector for ToolEnvsDetector {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn detect_project(&self, project: &Path) -> Result<Vec<Environment>, Errors> {
+        if !project_uses(project, self.config_files, self.pyproject_table) {
+            return Ok(vec![]);
+        }
+        let env_dir = project.join(self.env_dir);
+        let Ok(entries) = std::fs::read_dir(&env_dir) else {
+            return Ok(vec![]);
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| pyvenv::is_virtualenv(path))
+            .collect::<Vec<_>>();
+        paths.sort();
+        Ok(existing_paths(paths, self.name, project))
+    }
+}
+
+/// The plain old `.venv` next to the project files
+pub struct InProjectDetector;
+
+impl Detector for InProjectDetector {
+    fn name(&self) -> &'static str {
+        "in-project"
+    }
+
+    fn detect_project(&self, project: &Path) -> Result<Vec<Environment>, Errors> {
+        Ok(existing_paths(
+            [project.join(".venv")],
+            self.name(),
+            project,
+        ))
+    }
+}
@@ -0,0 +1,89 @@
+//! Honouring `.gitignore`, `.ignore` and `.sweepignore` files while walking, so the walk can skip
+//! build outputs, data directories and the like. Environments are usually ignored too, so the
+//! scanner still checks ignored directories for one before skipping them.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use ignore::Match;
+
+/// The ignore files we read in each directory, later ones win when they disagree
+pub const IGNORE_FILES: &[&str] = &[".gitignore", ".ignore", ".sweepignore"];
+
+/// The ignore files in the directories walked so far
+#[derive(Debug, Default)]
+pub struct IgnoreFiles {
+    /// `None` for directories without any ignore files
+    matchers: HashMap<PathBuf, Option<Gitignore>>,
+    debug: bool,
+}
+
+impl IgnoreFiles {
+    pub fn new(debug: bool) -> Self {
+        Self {
+            matchers: HashMap::new(),
+            debug,
+        }
+    }
+
+    /// the rules from the ignore files in a directory, read the first time it's asked about
+    fn matcher(&mut self, dir: &Path) -> Option<&Gitignore> {
+        let debug = self.debug;
+        self.matchers
+            .entry(dir.to_path_buf())
+            .or_insert_with(|| {
+                let mut builder = GitignoreBuilder::new(dir);
+                let mut found = false;
+                for name in IGNORE_FILES {
+                    let path = dir.join(name);
+                    if !path.is_file() {
+                        continue;
+                    }
+                    found = true;
+                    if let Some(err) = builder.add(&path) {
+                        if debug {
+                            eprintln!("Problem reading {}: {}", path.display(), err);
+                        }
+                    }
+                }
+                if !found {
+                    return None;
+                }
+                builder
+                    .build()
+                    .inspect_err(|err| {
+                        if debug {
+                            eprintln!(
+                                "Couldn't use the ignore files in {}: {}",
+                                dir.display(),
+                                err
+                            );
+                        }
+                    })
+                    .ok()
+            })
+            .as_ref()
+    }
+
+    /// whether the ignore files in the `depth` directories above a walked path ignore it. The
+    /// nearest directory with a rule about the path decides, like git.
+    pub fn is_ignored(&mut self, path: &Path, is_dir: bool, depth: usize) -> bool {
+        for dir in path.ancestors().skip(1).take(depth) {
+            match self
+                .matcher(dir)
+                .map(|matcher| matcher.matched(path, is_dir))
+            {
+                Some(Match::Ignore(_)) => return true,
+                Some(Match::Whitelist(_)) => return false,
+                Some(Match::None) | None => {}
+            }
+        }
+        false
+    }
+
+    /// forget the directories read so far, when moving on to another root
+    pub fn clear(&mut self) {
+        self.matchers.clear();
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use crate::env_var;
+
+/// Environment variables that point at the environment a process is running in
+const ENVIRONMENT_VARIABLES: &[&str] = &["VIRTUAL_ENV", "CONDA_PREFIX"];
+
+/// A snapshot of the paths running processes are using, to check environments against
+#[derive(Debug, Default)]
+pub struct Processes {
+    /// who's using it, and the path they're using
+    users: Vec<(String, PathBuf)>,
+}
+
+impl Processes {
+    /// looks at this process's environment, and on Linux at everything we can see in `/proc`
+    pub fn snapshot() -> Self {
+        let mut users = vec![];
+        for variable in ENVIRONMENT_VARIABLES {
+            if let Some(value) = env_var(variable) {
+                users.push((format!("this shell (${})", variable), value));
+            }
+        }
+        if let Ok(entries) = std::fs::read_dir("/proc") {
+            let own_pid = std::process::id().to_string();
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let pid = entry.file_name().to_string_lossy().to_string();
+                if pid == own_pid || !pid.chars().all(|c| c.is_ascii_digit()) {
+                    continue;
+                }
+                let process = entry.path();
+                let name = std::fs::read_to_string(process.join("comm"))
+                    .map(|comm| comm.trim().to_string())
+                    .unwrap_or_default();
+                let who = format!("pid {} ({})", pid, name);
+                users.extend(
+                    process_paths(&process)
+                        .into_iter()
+                        .map(|path| (who.clone(), path)),
+                );
+            }
+        }
+        Self { users }
+    }
+
+    /// who's using something inside `path`, if anyone
+    pub fn using(&self, path: &Path) -> Option<String> {
+        let canonical = path.canonicalize().ok()?;
+        self.users
+            .iter()
+            .find(|(_, used)| used.starts_with(&canonical) || used.starts_with(path))
+            .map(|(who, _)| who.clone())
+    }
+}
+
+/// the paths a process is using: its executable, working directory, command line, mapped files
+/// and any environment it has activated. Anything we aren't allowed to read is skipped.
+fn process_paths(process: &Path) -> HashSet<PathBuf> {
+    let mut paths = HashSet::new();
+    for link in ["exe", "cwd"] {
+        if let Ok(target) = std::fs::read_link(process.join(link)) {
+            paths.insert(target);
+        }
+    }
+    // the exe link resolves symlinks, so a venv's python shows up as the system one, but the
+    // command line still has the path it was started with
+    if let Ok(cmdline) = std::fs::read(process.join("cmdline")) {
+        paths.extend(
+            cmdline
+                .split(|byte| *byte == 0)
+                .map(|arg| String::from_utf8_lossy(arg).to_string())
+                .filter(|arg| arg.starts_with('/'))
+                .map(PathBuf::from),
+        );
+    }
+    if let Ok(maps) = std::fs::read_to_string(process.join("maps")) {
+        paths.extend(
+            maps.lines()
+                .filter_map(mapped_path)
+                .filter(|path| path.starts_with('/'))
+                .map(PathBuf::from),
+        );
+    }
+    if let Ok(environ) = std::fs::read(process.join("environ")) {
+        for variable in environ.split(|byte| *byte == 0) {
+            let variable = String::from_utf8_lossy(variable);
+            if let Some((key, value)) = variable.split_once('=') {
+                if ENVIRONMENT_VARIABLES.contains(&key) && !value.is_empty() {
+                    paths.insert(PathBuf::from(value));
+                }
+            }
+        }
+    }
+    paths
+}
+
+/// the path in a line of `/proc/<pid>/maps`, which is everything after the fifth field, spaces and
+/// all: `address perms offset dev inode pathname`
+fn mapped_path(line: &str) -> Option<&str> {
+    let path = line.splitn(6, char::is_whitespace).nth(5)?.trim_start();
+    Some(path).filter(|path| !path.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mapped_paths_keep_their_spaces() {
+        assert_eq!(
+            mapped_path("7f1c2a000000-7f1c2a021000 r--p 00000000 08:01 1234                       /home/u/my proj/.venv/lib/libfoo.so"),
+            Some("/home/u/my proj/.venv/lib/libfoo.so")
+        );
+        assert_eq!(
+            mapped_path(
+                "7ffd1b1e6000-7ffd1b207000 rw-p 00000000 00:00 0                          [stack]"
+            ),
+            Some("[stack]")
+        );
+        assert_eq!(
+            mapped_path("7f1c2a021000-7f1c2a030000 rw-p 00000000 00:00 0 "),
+            None
+        );
+        assert_eq!(
+            mapped_path("7f1c2a021000-7f1c2a030000 rw-p 00000000 00:00 0"),
+            None
+        );
+    }
+}
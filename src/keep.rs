@@ -0,0 +1,229 @@
+//! Protecting things from the sweep: `--exclude` globs stop the walk going into matching paths,
+//! and a [KEEP_MARKER] file or `[tool.python-sweep] keep = true` in a project's pyproject.toml
+//! means its environments are reported as kept instead of being removed.
+
+use std::path::{Path, PathBuf};
+
+use globset::{GlobBuilder, GlobSet, GlobSetBuilder};
+
+use crate::{Environment, Errors};
+
+/// A file in a project, or in an environment, that says to keep its environments
+pub const KEEP_MARKER: &str = ".python-sweep-keep";
+
+/// A set of exclude patterns. A pattern starting with `/` or `~/` matches from the root of the
+/// filesystem, anything else matches at any depth, so `vendor` and `work/*/venv` work anywhere.
+/// `*` doesn't match `/`, use `**` for that.
+#[derive(Debug, Clone, Default)]
+pub struct Excludes {
+    patterns: Vec<String>,
+    set: GlobSet,
+}
+
+impl Excludes {
+    pub fn new(patterns: &[String]) -> Result<Self, Errors> {
+        let mut builder = GlobSetBuilder::new();
+        for pattern in patterns {
+            builder.add(
+                GlobBuilder::new(&expand(pattern))
+                    .literal_separator(true)
+                    .build()
+                    .map_err(|err| {
+                        Errors::Other(format!("Invalid exclude pattern {:?}: {}", pattern, err))
+                    })?,
+            );
+        }
+        let set = builder
+            .build()
+            .map_err(|err| Errors::Other(format!("Invalid exclude patterns: {}", err)))?;
+        Ok(Self {
+            patterns: patterns.to_vec(),
+            set,
+        })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.patterns.is_empty()
+    }
+
+    /// whether the path itself matches a pattern
+    pub fn matches(&self, path: &Path) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        std::path::absolute(path).is_ok_and(|path| self.set.is_match(path))
+    }
+
+    /// whether the path or anything it's inside matches a pattern, for paths that didn't come
+    /// from the walk, like a tool's cache directory
+    pub fn covers(&self, path: &Path) -> bool {
+        if self.is_empty() {
+            return false;
+        }
+        std::path::absolute(path)
+            .is_ok_and(|path| path.ancestors().any(|path| self.set.is_match(path)))
+    }
+}
+
+/// anchors `~/` at the home directory, and lets relative patterns match at any depth
+fn expand(pattern: &str) -> String {
+    if let Some(rest) = pattern.strip_prefix("~/") {
+        if let Some(home) = std::env::var_os("HOME") {
+            return PathBuf::from(home).join(rest).to_string_lossy().to_string();
+        }
+    }
+    match pattern.starts_with('/') || pattern.starts_with("**") {
+        true => pattern.to_string(),
+        false => format!("**/{}", pattern),
+    }
+}
+
+/// checks an exclude pattern from the command line
+pub fn parse_exclude(pattern: &str) -> Result<String, String> {
+    Excludes::new(&[pattern.to_string()])
+        .map(|_| pattern.to_string())
+        .map_err(|err| err.to_string())
+}
+
+/// whether a project's pyproject.toml has `[tool.python-sweep] keep = true`
+fn pyproject_keeps(project: &Path) -> bool {
+    let Ok(contents) = std::fs::read_to_string(project.join("pyproject.toml")) else {
+        return false;
+    };
+    contents
+        .parse::<toml::Table>()
+        .ok()
+        .and_then(|pyproject| {
+            pyproject
+                .get("tool")?
+                .get("python-sweep")?
+                .get("keep")?
+                .as_bool()
+        })
+        .unwrap_or(false)
+}
+
+/// what says to keep an environment, if anything does. That's a [KEEP_MARKER] in the environment
+/// or its project, or the project's pyproject.toml. Environments found without a project count
+/// the directory they're in as their project.
+pub fn kept_by(environment: &Environment) -> Option<PathBuf> {
+    let project = environment
+        .project
+        .as_deref()
+        .or_else(|| environment.path.parent())?;
+    [&environment.path, project]
+        .into_iter()
+        .map(|dir| dir.join(KEEP_MARKER))
+        .find(|marker| marker.exists())
+        .or_else(|| pyproject_keeps(project).then(|| project.join("pyproject.toml")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn excluding(patterns: &[&str]) -> Excludes {
+        Excludes::new(&patterns.iter().map(|p| p.to_string()).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn relative_patterns_match_at_any_depth() {
+        let excludes = excluding(&["vendor"]);
+        assert!(excludes.matches(Path::new("/vendor")));
+        assert!(excludes.matches(Path::new("/home/me/src/app/vendor")));
+        assert!(!excludes.matches(Path::new("/home/me/src/app/vendored")));
+        assert!(!excludes.matches(Path::new("/home/me/src/app/vendor/lib")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn absolute_patterns_match_from_the_root() {
+        let excludes = excluding(&["/srv/keep"]);
+        assert!(excludes.matches(Path::new("/srv/keep")));
+        assert!(!excludes.matches(Path::new("/home/me/srv/keep")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn home_patterns_match_in_the_home_directory() {
+        let Some(home) = std::env::var_os("HOME").map(PathBuf::from) else {
+            return;
+        };
+        let excludes = excluding(&["~/src/keep-me"]);
+        assert!(excludes.matches(&home.join("src").join("keep-me")));
+        assert!(!excludes.matches(Path::new("/elsewhere/src/keep-me")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn a_star_doesnt_match_a_slash() {
+        let excludes = excluding(&["work/*/venv"]);
+        assert!(excludes.matches(Path::new("/home/me/work/app/venv")));
+        assert!(!excludes.matches(Path::new("/home/me/work/app/sub/venv")));
+        let excludes = excluding(&["work/**/venv"]);
+        assert!(excludes.matches(Path::new("/home/me/work/app/sub/venv")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn covers_what_is_inside_a_match() {
+        let excludes = excluding(&["vendor"]);
+        assert!(excludes.covers(Path::new("/home/me/app/vendor/lib/.venv")));
+        assert!(!excludes.covers(Path::new("/home/me/app/lib/.venv")));
+    }
+
+    #[test]
+    fn nothing_is_excluded_without_patterns() {
+        assert!(!Excludes::default().matches(Path::new("/vendor")));
+        assert!(!Excludes::default().covers(Path::new("/vendor")));
+    }
+
+    /// a project with an environment in it, and the environment as found with or without its
+    /// project
+    fn project(dir: &Path, with_project: bool) -> Environment {
+        let project = dir.join("app");
+        std::fs::create_dir_all(project.join(".venv")).unwrap();
+        let environment = Environment::new(project.join(".venv"), "in-project");
+        match with_project {
+            true => environment.with_project(&project),
+            false => environment,
+        }
+    }
+
+    #[test]
+    fn kept_by_a_marker_in_the_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let environment = project(dir.path(), true);
+        assert_eq!(kept_by(&environment), None);
+        std::fs::write(environment.path.join(KEEP_MARKER), "").unwrap();
+        assert_eq!(
+            kept_by(&environment),
+            Some(environment.path.join(KEEP_MARKER))
+        );
+    }
+
+    #[test]
+    fn kept_by_a_marker_in_the_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let environment = project(dir.path(), true);
+        let marker = dir.path().join("app").join(KEEP_MARKER);
+        std::fs::write(&marker, "").unwrap();
+        assert_eq!(kept_by(&environment), Some(marker.clone()));
+        // without a project, the directory the environment is in counts as its project
+        assert_eq!(kept_by(&project(dir.path(), false)), Some(marker));
+    }
+
+    #[test]
+    fn kept_by_pyproject() {
+        let dir = tempfile::tempdir().unwrap();
+        let environment = project(dir.path(), true);
+        let pyproject = dir.path().join("app").join("pyproject.toml");
+        std::fs::write(&pyproject, "[tool.python-sweep]\nkeep = false\n").unwrap();
+        assert_eq!(kept_by(&environment), None);
+        std::fs::write(&pyproject, "[project]\nname = \"app\"\n").unwrap();
+        assert_eq!(kept_by(&environment), None);
+        std::fs::write(&pyproject, "[tool.python-sweep]\nkeep = true\n").unwrap();
+        assert_eq!(kept_by(&environment), Some(pyproject));
+    }
+}
@@ -0,0 +1,147 @@
+//! Find (and optionally remove) Python virtual environments to reclaim disk space.
+//!
+//! Build a [Scanner] over one or more root paths, iterate over the [Environment]s it finds, and
+//! pass the ones you don't want any more to [delete::delete_environment].
+
+pub mod age;
+pub mod config;
+pub mod delete;
+pub mod detectors;
+pub mod ignores;
+pub mod inuse;
+pub mod keep;
+pub mod markers;
+pub mod mounts;
+pub mod names;
+pub mod output;
+pub mod pipenv;
+pub mod plan;
+pub mod poetry;
+pub mod pyvenv;
+pub mod safety;
+pub mod scan;
+pub mod size;
+pub mod trash;
+pub mod virtualenvwrapper;
+
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+pub use detectors::{Detector, Environment};
+pub use scan::{Scanner, SortOrder};
+
+/// Everything that can go wrong finding or removing an environment
+#[derive(Debug)]
+pub enum Errors {
+    /// We weren't allowed to do something to a path
+    PermissionDenied {
+        action: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// Removing an environment failed after some of it was already gone
+    PartiallyDeleted {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// A tool we ran to find environments failed
+    Subprocess { program: String, message: String },
+    /// A detector found environments but had a problem too, like poetry timing out when asked
+    /// which one it's using
+    Incomplete {
+        found: Vec<Environment>,
+        source: Box<Errors>,
+    },
+    /// The path isn't an environment we're willing to remove
+    InvalidVenv { path: PathBuf, reason: String },
+    /// A running process is using the environment
+    InUse { path: PathBuf, by: String },
+    /// Any other problem reading or writing a path
+    Io {
+        action: &'static str,
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// Anything else, like a plan file we can't understand
+    Other(String),
+}
+
+impl Errors {
+    /// wraps an IO error from trying to `action` (eg "delete") a path, picking out permission
+    /// problems since they're the ones the user can usually fix
+    pub fn io(action: &'static str, path: &Path, source: std::io::Error) -> Self {
+        let path = path.to_path_buf();
+        match source.kind() {
+            std::io::ErrorKind::PermissionDenied => Self::PermissionDenied {
+                action,
+                path,
+                source,
+            },
+            _ => Self::Io {
+                action,
+                path,
+                source,
+            },
+        }
+    }
+}
+
+impl fmt::Display for Errors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Errors::Other(message) => write!(f, "{}", message),
+            Errors::PermissionDenied { action, path, .. } => {
+                write!(
+                    f,
+                    "Permission denied trying to {} {}",
+                    action,
+                    path.display()
+                )
+            }
+            Errors::PartiallyDeleted { path, source } => write!(
+                f,
+                "Only part of {} was deleted, the rest is still there: {}",
+                path.display(),
+                source
+            ),
+            Errors::Subprocess { program, message } => {
+                write!(f, "Running {} failed: {}", program, message)
+            }
+            Errors::Incomplete { source, .. } => write!(f, "{}", source),
+            Errors::InvalidVenv { path, reason } => {
+                write!(f, "Refusing to remove {}: {}", path.display(), reason)
+            }
+            Errors::InUse { path, by } => write!(
+                f,
+                "Not removing {}, it's in use by {}. Use --force to remove it anyway",
+                path.display(),
+                by
+            ),
+            Errors::Io {
+                action,
+                path,
+                source,
+            } => write!(f, "Failed to {} {}: {}", action, path.display(), source),
+        }
+    }
+}
+
+impl std::error::Error for Errors {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Errors::PermissionDenied { source, .. }
+            | Errors::PartiallyDeleted { source, .. }
+            | Errors::Io { source, .. } => Some(source),
+            Errors::Incomplete { source, .. } => Some(source.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+/// an environment variable, if it's set to something. The tools we follow treat an empty one as
+/// unset.
+pub fn env_var(name: &str) -> Option<PathBuf> {
+    std::env::var_os(name)
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+}
@@ -1,257 +1,687 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
 use std::sync::{Arc, RwLock};
 
-use clap::Parser;
-use std::process::Command;
-use walkdir::WalkDir;
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand};
 
-#[allow(dead_code)]
-#[derive(Debug)]
-enum Errors {
-    NotReallyAnError(String),
-    ActuallyAnError(String),
-}
+use python_sweep::config::Config;
+use python_sweep::delete::delete_environment;
+use python_sweep::inuse::Processes;
+use python_sweep::keep::{self, parse_exclude};
+use python_sweep::output::{Action, OutputFormat, Reporter};
+use python_sweep::plan::{Plan, PlanEntry};
+use python_sweep::size::{human_size, parse_size, SizeMode};
+use python_sweep::trash::trash_environment;
+use python_sweep::{age, detectors, Environment, Errors, SortOrder};
 
 #[derive(Parser, Debug)]
-#[clap(version)]
+#[clap(version, about)]
 struct Cli {
-    /// Path to search for virtualenvs
-    path: Option<PathBuf>,
+    #[clap(flatten)]
+    scan: ScanArgs,
+
     /// Delete the virtualenvs instead of just printing them
-    #[clap(long, short)]
+    #[clap(long, short, overrides_with = "no_delete")]
     delete: bool,
-    /// Maximum depth to recurse into the directory
-    #[clap(long, short)]
-    max_depth: Option<usize>,
-
-    /// Go deep - if we find a pyproject.toml, we won't go deeper into a dir structure
-    #[clap(long, short = 'D')]
-    deep: bool,
+    /// Don't delete anything, even if a config file says to
+    #[clap(long, overrides_with = "delete")]
+    no_delete: bool,
+    /// Move the virtualenvs to the trash instead of deleting them, so they can be restored
+    #[clap(long, conflicts_with = "delete", overrides_with = "no_trash")]
+    trash: bool,
+    /// Don't move anything to the trash, even if a config file says to
+    #[clap(long, overrides_with = "trash")]
+    no_trash: bool,
+    /// Only look, even if a config file says to remove things. Exits with status 3 if any virtualenvs are found, for CI or login scripts
+    #[clap(long, conflicts_with_all = ["delete", "trash"])]
+    check: bool,
 
     /// Debug mode
-    #[clap(long = "debug")]
+    #[clap(long = "debug", global = true)]
     debug: bool,
 
     /// Non-interactive
     #[clap(long = "non-interactive", short)]
     non_interactive: bool,
+
+    /// Remove virtualenvs even if a running process is using them
+    #[clap(long, global = true)]
+    force: bool,
+
+    /// Output format
+    #[clap(long, value_enum, default_value_t, global = true)]
+    format: OutputFormat,
+
+    #[clap(subcommand)]
+    command: Option<Commands>,
 }
 
-/// gets the size on disk of a directory
-fn get_size_on_disk(path: &PathBuf) -> u64 {
-    let mut size = 0;
-    for entry in WalkDir::new(path) {
-        let entry = match entry {
-            Ok(val) => val,
-            Err(_err) => {
-                // eprintln!("Error getting direntry, did you just delete the parent? {:?}", err);
-                continue;
-            }
-        };
-        if entry.path().is_file() {
-            size += entry.metadata().unwrap().len();
+/// How the run went, as the exit status. These are documented in the README, so don't renumber
+/// them. Bad arguments exit with 2, from clap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Nothing was found, or a command that doesn't look for virtualenvs worked
+    Success = 0,
+    /// Nothing worked, or we couldn't get started
+    Failed = 1,
+    /// Virtualenvs were found and some are still there, because we were only listing them, or
+    /// checking for them, or the user said not to remove them
+    Found = 3,
+    /// Everything that was found was removed
+    Removed = 4,
+    /// Some things failed, but others worked
+    PartlyFailed = 5,
+    /// The user pressed Ctrl+C
+    Aborted = 130,
+}
+
+impl Outcome {
+    /// sums up a run from the number of problems, virtualenvs left where they were, and
+    /// virtualenvs removed. Kept virtualenvs aren't counted, they're meant to be left alone.
+    fn new(failures: usize, left: usize, removed: usize) -> Self {
+        match (failures, left, removed) {
+            (0, 0, 0) => Outcome::Success,
+            (0, 0, _) => Outcome::Removed,
+            (0, _, _) => Outcome::Found,
+            (_, 0, 0) => Outcome::Failed,
+            (_, _, _) => Outcome::PartlyFailed,
         }
     }
-    size
 }
 
-/// looks for a virtualenv
-fn check_path(
-    checked_paths: &mut Vec<PathBuf>,
-    cli: &Cli,
-    entry: walkdir::DirEntry,
-) -> Result<PathBuf, Errors> {
-    if !cli.deep {
-        for checked_path in checked_paths.iter() {
-            if entry.path().starts_with(checked_path) {
-                return Err(Errors::NotReallyAnError(format!(
-                    "Already checked parent of {}",
-                    entry.path().display()
-                )));
-            }
+#[derive(Subcommand, Debug)]
+enum Commands {
+    /// Scan for virtualenvs and write them to a plan file to review, instead of removing them
+    Plan {
+        #[clap(flatten)]
+        scan: Box<ScanArgs>,
+        /// Where to write the plan
+        #[clap(long, short)]
+        out: PathBuf,
+    },
+    /// Remove exactly the virtualenvs in a plan file, skipping any that changed since it was made
+    Apply {
+        /// The plan file written by `plan`
+        plan: PathBuf,
+        /// Move the virtualenvs to the trash instead of deleting them, so they can be restored
+        #[clap(long)]
+        trash: bool,
+    },
+    /// Work with the config files
+    Config {
+        #[clap(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommands {
+    /// Print the configuration the config files and any options before `config` add up to
+    Show,
+}
+
+/// Options for finding virtualenvs
+#[derive(clap::Args, Debug)]
+struct ScanArgs {
+    /// Path to search for virtualenvs
+    path: Option<PathBuf>,
+    /// Don't look in paths matching this glob, eg `~/src/keep-me` or `vendor`. Can be repeated
+    #[clap(long, value_name = "GLOB", value_parser = parse_exclude)]
+    exclude: Vec<String>,
+    /// Maximum depth to recurse into the directory
+    #[clap(long, short)]
+    max_depth: Option<usize>,
+
+    /// Go deep - without this, once we find a project marker we won't go deeper into a dir structure
+    #[clap(long, short = 'D', overrides_with = "no_deep")]
+    deep: bool,
+    /// Stop at project markers, even if a config file says to go deep
+    #[clap(long, overrides_with = "deep")]
+    no_deep: bool,
+
+    /// Don't walk into what .gitignore, .ignore and .sweepignore files ignore, apart from virtualenvs
+    #[clap(long, overrides_with = "no_respect_ignores")]
+    respect_ignores: bool,
+    /// Walk into what ignore files ignore, even if a config file says not to
+    #[clap(long, overrides_with = "respect_ignores")]
+    no_respect_ignores: bool,
+
+    /// Don't walk into other filesystems mounted inside the path, like network shares
+    #[clap(long, overrides_with = "no_one_file_system")]
+    one_file_system: bool,
+    /// Walk into other filesystems, even if a config file says not to
+    #[clap(long, overrides_with = "one_file_system")]
+    no_one_file_system: bool,
+
+    /// Walk into pseudo filesystems like /proc, /sys and overlay mounts, which are skipped by default
+    #[clap(long, overrides_with = "no_pseudo_filesystems")]
+    pseudo_filesystems: bool,
+    /// Skip pseudo filesystems, even if a config file says to walk them
+    #[clap(long, overrides_with = "pseudo_filesystems")]
+    no_pseudo_filesystems: bool,
+
+    /// Instead of searching a path, look in tools' own directories (like poetry's cache) for virtualenvs whose project has been deleted
+    #[clap(long, overrides_with = "no_orphans")]
+    orphans: bool,
+    /// Search a path, even if a config file says to look for orphans
+    #[clap(long, overrides_with = "orphans")]
+    no_orphans: bool,
+
+    /// Leave out the virtualenv poetry is using for each project, so only the ones for other Python versions are swept
+    #[clap(long, overrides_with = "no_keep_active")]
+    keep_active: bool,
+    /// Include the virtualenv poetry is using, even if a config file says to leave it out
+    #[clap(long, overrides_with = "keep_active")]
+    no_keep_active: bool,
+
+    /// Filename that marks a project directory, supports `*` wildcards. Can be repeated, replaces the defaults like pyproject.toml and requirements*.txt
+    #[clap(long = "marker", value_name = "PATTERN")]
+    markers: Vec<String>,
+
+    /// Only use these detectors, can be repeated
+    #[clap(long = "detector", value_name = "NAME", value_parser = clap::builder::PossibleValuesParser::new(detectors::DETECTOR_NAMES))]
+    detectors: Vec<String>,
+
+    /// Don't use these detectors, can be repeated
+    #[clap(long = "skip-detector", value_name = "NAME", value_parser = clap::builder::PossibleValuesParser::new(detectors::DETECTOR_NAMES))]
+    skip_detectors: Vec<String>,
+
+    /// Number of threads working out sizes, more than one finishes the scan before showing results [default: 1]
+    #[clap(long, short)]
+    jobs: Option<usize>,
+
+    /// Finish the scan before showing results, then sort them. Defaults to path when --jobs is more than 1
+    #[clap(long, value_enum)]
+    sort: Option<SortOrder>,
+
+    /// How to measure environments [default: apparent]
+    #[clap(long, value_enum)]
+    size_mode: Option<SizeMode>,
+
+    /// Only include environments that haven't been used, and whose projects haven't been changed, for this long, eg `30d`
+    #[clap(long, value_name = "AGE", value_parser = age::parse_age)]
+    older_than: Option<std::time::Duration>,
+
+    /// Only include environments at least this big, eg `200MB`
+    #[clap(long, value_name = "SIZE", value_parser = parse_size)]
+    min_size: Option<u64>,
+
+    /// Only include the N largest environments, sorted largest first
+    #[clap(long, value_name = "N")]
+    top: Option<usize>,
+}
+
+/// a flag and its `--no-` negation as a setting, unset if neither was given. Clap makes sure only
+/// the last of the two given is set.
+fn flag(on: bool, off: bool) -> Option<bool> {
+    match (on, off) {
+        (true, _) => Some(true),
+        (false, true) => Some(false),
+        (false, false) => None,
+    }
+}
+
+impl ScanArgs {
+    /// the options given on the command line, to go on top of the config files
+    fn config(&self) -> Config {
+        let non_empty = |values: &Vec<String>| Some(values.clone()).filter(|v| !v.is_empty());
+        Config {
+            roots: self.path.iter().cloned().collect(),
+            exclude: self.exclude.clone(),
+            max_depth: self.max_depth,
+            deep: flag(self.deep, self.no_deep),
+            respect_ignores: flag(self.respect_ignores, self.no_respect_ignores),
+            one_file_system: flag(self.one_file_system, self.no_one_file_system),
+            pseudo_filesystems: flag(self.pseudo_filesystems, self.no_pseudo_filesystems),
+            orphans: flag(self.orphans, self.no_orphans),
+            keep_active: flag(self.keep_active, self.no_keep_active),
+            markers: non_empty(&self.markers),
+            detectors: non_empty(&self.detectors),
+            skip_detectors: non_empty(&self.skip_detectors),
+            jobs: self.jobs,
+            sort: self.sort,
+            size_mode: self.size_mode,
+            older_than: self
+                .older_than
+                .map(|older_than| humantime::format_duration(older_than).to_string()),
+            min_size: self.min_size.map(|min_size| min_size.to_string()),
+            top: self.top,
+            delete: None,
+            trash: None,
         }
     }
-    if entry.file_name() == "pyproject.toml" {
-        checked_paths.push(
-            entry
-                .path()
-                .parent()
-                .expect("Can't get parent of a known file?")
-                .to_path_buf(),
-        );
-        let project_path = entry
-            .path()
-            .parent()
-            .expect("Can't find the parent path for a file we just found?");
-        if cli.debug {
-            eprintln!("Project path: {:?}", project_path);
+}
+
+impl Cli {
+    /// the top level options given on the command line, to go on top of the config files
+    fn config(&self) -> Config {
+        // asking for one kind of removal, or none with --check, turns off the others
+        Config {
+            delete: flag(self.delete, self.no_delete || self.trash || self.check),
+            trash: flag(self.trash, self.no_trash || self.delete || self.check),
+            ..self.scan.config()
         }
-        let venv = project_path.join(".venv");
-        if venv.exists() {
-            if cli.debug {
-                eprintln!("venv path found: {:?}", venv);
-            }
-            Ok(venv)
-        } else if which::which("poetry").is_ok() {
-            // try to use poetry
-            if cli.debug {
-                eprintln!("venv path not found, trying to run poetry");
-            }
+    }
 
-            let output = match Command::new("poetry")
-                .args([
-                    "env",
-                    "info",
-                    "--path",
-                    "--directory",
-                    &project_path.display().to_string(),
-                ])
-                .output()
-            {
-                Ok(val) => val,
-                Err(e) => {
-                    return Err(Errors::NotReallyAnError(format!(
-                        "Failed to execute poetry command: {:?}",
-                        e
-                    )));
-                }
-            };
+    /// the scan options for `plan`, which can go before or after it. The ones after win.
+    fn plan_config(&self, scan: &ScanArgs) -> Config {
+        self.scan.config().merge(scan.config())
+    }
+}
+
+/// reads the config files that apply to `path`, or the current directory, and puts the command
+/// line options on top. Returns the files that were read too.
+fn settings(path: Option<&Path>, over: Config) -> Result<(Config, Vec<PathBuf>), Errors> {
+    let (config, sources) = Config::discover(path.unwrap_or(Path::new(".")))?;
+    Ok((config.merge(over), sources))
+}
 
-            if output.status.success() {
-                let venv_path = String::from_utf8_lossy(&output.stdout);
-                if cli.debug {
-                    eprintln!("Virtualenv path from poetry: {:?}", venv_path.trim());
+/// like [settings], but reports any problem reading the config files
+fn load_settings(cli: &Cli, path: Option<&Path>, over: Config) -> Option<Config> {
+    match settings(path, over) {
+        Ok((config, sources)) => {
+            if cli.debug {
+                for source in sources.iter() {
+                    eprintln!("Read config from {}", source.display());
                 }
-                Ok(PathBuf::from(venv_path.trim()))
-            } else {
-                Err(Errors::NotReallyAnError(format!(
-                    "Failed to get venv path from poetry: {:?}",
-                    output.stderr
-                )))
             }
-        } else {
-            Err(Errors::NotReallyAnError(
-                "Don't have any other way to ".to_string(),
-            ))
+            Some(config)
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            None
         }
-    } else {
-        Err(Errors::NotReallyAnError("Not pyproject.toml".to_string()))
     }
 }
 
-fn main() {
-    let cli = Cli::parse();
-    let path = &cli.path.clone().unwrap_or_else(|| PathBuf::from("."));
+/// deletes or trashes an environment, returning the number of bytes removed. Unless we're
+/// forcing it, anything a running process is using right now is left alone.
+fn remove(environment: &Environment, action: Action, cli: &Cli) -> Result<u64, Errors> {
+    if !cli.force {
+        if let Some(who) = Processes::snapshot().using(&environment.path) {
+            return Err(Errors::InUse {
+                path: environment.path.clone(),
+                by: who,
+            });
+        }
+    }
     if cli.debug {
-        eprintln!("Walking path: {:?}", path);
+        eprintln!("Removing {}", environment.path.display());
     }
-    let mut walker = WalkDir::new(path);
+    match action {
+        Action::Trashed => trash_environment(environment),
+        _ => delete_environment(environment),
+    }
+}
 
-    let mut checked_paths = vec![];
+/// finds, and maybe removes, virtualenvs
+fn sweep(cli: &Cli) -> Outcome {
+    let Some(config) = load_settings(cli, cli.scan.path.as_deref(), cli.config()) else {
+        return Outcome::Failed;
+    };
+    let scanner = match config.scanner(cli.debug) {
+        Ok(scanner) => scanner.check_in_use(true),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return Outcome::Failed;
+        }
+    };
+
+    let action = match (
+        config.delete.unwrap_or_default(),
+        config.trash.unwrap_or_default(),
+    ) {
+        (_, true) => Action::Trashed,
+        (true, false) => Action::Deleted,
+        (false, false) => Action::Found,
+    };
 
     let total_deleted = Arc::new(RwLock::new(0));
     let total_deleted_callback = total_deleted.clone();
     ctrlc::set_handler(move || {
         eprintln!("Received Ctrl+C, exiting...");
-        if cli.delete {
-            let human_readable_size = byte_unit::Byte::from_u64(
+        if action != Action::Found {
+            let human_readable_size = human_size(
                 total_deleted_callback
                     .read()
                     .expect("Failed to get total deleted")
                     .to_owned(),
-            )
-            .get_appropriate_unit(byte_unit::UnitType::Decimal)
-            .to_string();
-            eprintln!("Deleted {} of virtualenvs", human_readable_size);
-            std::process::exit(0);
+            );
+            match action {
+                Action::Trashed => {
+                    eprintln!("Moved {} of virtualenvs to the trash", human_readable_size)
+                }
+                _ => eprintln!("Deleted {} of virtualenvs", human_readable_size),
+            }
         }
+        std::process::exit(Outcome::Aborted as i32);
     })
     .expect("Error setting Ctrl-C handler");
 
-    if let Some(max_depth) = &cli.max_depth {
-        walker = walker.max_depth(*max_depth);
+    let mut reporter = Reporter::new(cli.format);
+    let mut left = 0;
+    let mut removed = 0;
+    let mut aborted = false;
+    for found in scanner.scan() {
+        let found = match found {
+            Ok(val) => val,
+            Err(err) => {
+                reporter.failed(err);
+                continue;
+            }
+        };
+        if found.kept_by.is_some() {
+            reporter.report(&found, Action::Kept);
+            continue;
+        }
+        if action != Action::Found && found.in_use.is_some() && !cli.force {
+            left += 1;
+            reporter.report(&found, Action::Found);
+        } else if action != Action::Found {
+            let doit = match cli.non_interactive {
+                true => true,
+                false => {
+                    let question = match action {
+                        Action::Trashed => "Move this to the trash?",
+                        _ => "Delete this?",
+                    };
+                    let res = dialoguer::Confirm::new()
+                        .with_prompt(format!(
+                            "{} {} ({}) [{}]",
+                            question,
+                            found.path.display(),
+                            Reporter::human_sizes(&found),
+                            Reporter::details(&found)
+                        ))
+                        .interact();
+                    match res {
+                        Ok(val) => val,
+                        // the prompt reads keys in raw mode, so Ctrl+C shows up here, not as a signal
+                        Err(dialoguer::Error::IO(err))
+                            if err.kind() == std::io::ErrorKind::Interrupted =>
+                        {
+                            aborted = true;
+                            break;
+                        }
+                        Err(err) => {
+                            reporter.failed(Errors::Other(format!(
+                                "Error getting response from user: {}",
+                                err
+                            )));
+                            break;
+                        }
+                    }
+                }
+            };
+
+            if doit {
+                match remove(&found, action, cli) {
+                    Ok(bytes) => {
+                        let mut writer = total_deleted.write().expect("Failed to get write lock");
+                        *writer += bytes;
+                        removed += 1;
+                        reporter.report(&found, action);
+                    }
+                    Err(err) => reporter.failed(err),
+                }
+            } else {
+                left += 1;
+                reporter.report(&found, Action::Found);
+            }
+        } else {
+            left += 1;
+            reporter.report(&found, Action::Found);
+        }
+    }
+    match Outcome::new(reporter.finish(action), left, removed) {
+        _ if aborted => Outcome::Aborted,
+        outcome => outcome,
     }
+}
 
-    for entry in walker {
-        let entry = match entry {
+/// scans and writes what we found to a plan file
+fn plan(cli: &Cli, scan: &ScanArgs, out: &Path) -> Outcome {
+    let path = scan.path.as_deref().or(cli.scan.path.as_deref());
+    let Some(config) = load_settings(cli, path, cli.plan_config(scan)) else {
+        return Outcome::Failed;
+    };
+    let mut plan = Plan::default();
+    let mut reporter = Reporter::new(cli.format);
+    let scanner = match config.scanner(cli.debug) {
+        Ok(scanner) => scanner.check_in_use(true),
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return Outcome::Failed;
+        }
+    };
+    for found in scanner.scan() {
+        let found = match found {
             Ok(val) => val,
             Err(err) => {
-                if cli.debug {
-                    eprintln!(
-                        "Error getting direntry, did you just delete the parent? {:?}",
-                        err
-                    );
-                }
+                reporter.failed(err);
                 continue;
             }
         };
-        if !entry.path().exists() {
-            if cli.debug {
-                eprintln!("Path doesn't exist: {:?}", entry.path());
-            }
+        if found.kept_by.is_some() {
+            reporter.report(&found, Action::Kept);
             continue;
         }
-
-        match check_path(&mut checked_paths, &cli, entry) {
+        match PlanEntry::new(&found) {
+            Ok(entry) => plan.environments.push(entry),
             Err(err) => {
-                if let Errors::ActuallyAnError(err) = err {
-                    eprintln!("Error: {:?}", err);
-                } else if cli.debug {
-                    eprintln!("{:?}", err);
-                }
+                reporter.failed(err);
+                continue;
             }
-            Ok(val) => {
-                let dir_size = get_size_on_disk(&val);
-                // turn dir_size into a human readable string
-                let human_readable_size = byte_unit::Byte::from_u64(dir_size)
-                    .get_appropriate_unit(byte_unit::UnitType::Decimal)
-                    .to_string();
-                if cli.delete {
-                    let doit = match cli.non_interactive {
-                        true => true,
-                        false => {
-                            let res = dialoguer::Confirm::new()
-                                .with_prompt(format!(
-                                    "Delete this? {} ({})",
-                                    val.display(),
-                                    human_readable_size
-                                ))
-                                .interact();
-                            match res {
-                                Ok(val) => val,
-                                Err(err) => {
-                                    eprintln!("Error getting response from user: {:?}", err);
-                                    return;
-                                }
-                            }
-                        }
-                    };
+        }
+        reporter.report(&found, Action::Found);
+    }
+    match plan.save(out) {
+        Ok(()) => eprintln!(
+            "Wrote {} virtualenvs to {}",
+            plan.environments.len(),
+            out.display()
+        ),
+        Err(err) => reporter.failed(err),
+    }
+    let left = plan.environments.len();
+    Outcome::new(reporter.finish(Action::Found), left, 0)
+}
 
-                    if doit {
-                        if cli.debug {
-                            eprintln!("Deleting {}", val.display());
-                        }
-                        std::fs::remove_dir_all(&val).expect("Failed to delete venv");
-                        println!("Deleted {:?} ({})", val.display(), human_readable_size);
-                        let mut writer = total_deleted.write().expect("Failed to get write lock");
+/// removes the environments in a plan file, if they haven't changed
+fn apply(cli: &Cli, plan_path: &Path, trash: bool) -> Outcome {
+    let removal = cli.config();
+    let over = Config {
+        delete: removal.delete,
+        trash: match trash {
+            true => Some(true),
+            false => removal.trash,
+        },
+        ..Config::default()
+    };
+    let Some(config) = load_settings(cli, None, over) else {
+        return Outcome::Failed;
+    };
+    let trash = config.trash.unwrap_or_default();
+    let plan = match Plan::load(plan_path) {
+        Ok(plan) => plan,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return Outcome::Failed;
+        }
+    };
+    let action = match trash {
+        true => Action::Trashed,
+        false => Action::Deleted,
+    };
+    let mut reporter = Reporter::new(cli.format);
+    let mut removed = 0;
+    for entry in plan.environments.iter() {
+        if let Err(err) = entry.verify() {
+            reporter.failed(err);
+            continue;
+        }
+        let mut environment = entry.environment();
+        // someone might have added a keep marker since the plan was made
+        environment.kept_by = keep::kept_by(&environment);
+        if environment.kept_by.is_some() {
+            reporter.report(&environment, Action::Kept);
+            continue;
+        }
+        match remove(&environment, action, cli) {
+            Ok(_) => {
+                removed += 1;
+                reporter.report(&environment, action);
+            }
+            Err(err) => reporter.failed(err),
+        }
+    }
+    Outcome::new(reporter.finish(action), 0, removed)
+}
 
-                        *writer += dir_size;
+/// prints the merged configuration as TOML, with the files it came from
+fn show_config(cli: &Cli) -> Outcome {
+    let (config, sources) = match settings(cli.scan.path.as_deref(), cli.config()) {
+        Ok(settings) => settings,
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            return Outcome::Failed;
+        }
+    };
+    for source in sources.iter() {
+        println!("# from {}", source.display());
+    }
+    match toml::to_string_pretty(&config) {
+        Ok(output) => {
+            print!("{}", output);
+            Outcome::Success
+        }
+        Err(err) => {
+            eprintln!("Error: Failed to write config: {}", err);
+            Outcome::Failed
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let outcome = match &cli.command {
+        None => sweep(&cli),
+        Some(Commands::Plan { scan, out }) => {
+            // these are top level options, so clap can't tell they don't go with `plan`
+            if cli.delete || cli.trash {
+                Cli::command()
+                    .error(
+                        ErrorKind::ArgumentConflict,
+                        "--delete and --trash can't be used with plan, apply the plan to remove what it found",
+                    )
+                    .exit();
+            }
+            plan(&cli, scan, out)
+        }
+        Some(Commands::Apply { plan, trash }) => {
+            // these are top level options, so clap can't tell they don't go with `apply`
+            let scanning = cli.scan.config() != Config::default();
+            if cli.check || (cli.delete && *trash) || scanning {
+                let message = match (cli.check, scanning) {
+                    (true, _) => "--check can't be used with apply",
+                    (false, true) => {
+                        "options for finding virtualenvs can't be used with apply, it removes what's in the plan"
                     }
-                } else {
-                    let mut writer = total_deleted.write().expect("Failed to get write lock");
-                    *writer += dir_size;
-                    println!("Found {:?} ({})", val, human_readable_size);
-                }
+                    (false, false) => "--delete can't be used with apply --trash",
+                };
+                Cli::command()
+                    .error(ErrorKind::ArgumentConflict, message)
+                    .exit();
             }
-        };
+            apply(&cli, plan, *trash)
+        }
+        Some(Commands::Config {
+            command: ConfigCommands::Show,
+        }) => show_config(&cli),
+    };
+    ExitCode::from(outcome as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(args: &[&str]) -> Config {
+        Cli::try_parse_from(["python-sweep"].iter().chain(args))
+            .unwrap()
+            .config()
     }
-    let human_readable_size =
-        byte_unit::Byte::from_u64(*total_deleted.read().expect("Failed to get reader"))
-            .get_appropriate_unit(byte_unit::UnitType::Decimal)
-            .to_string();
-    if cli.delete {
-        eprintln!("Deleted {} of virtualenvs", human_readable_size);
-    } else {
-        eprintln!("Found {} of virtualenvs", human_readable_size);
+
+    #[test]
+    fn outcomes() {
+        assert_eq!(Outcome::new(0, 0, 0), Outcome::Success);
+        assert_eq!(Outcome::new(0, 2, 0), Outcome::Found);
+        assert_eq!(Outcome::new(0, 1, 1), Outcome::Found);
+        assert_eq!(Outcome::new(0, 0, 2), Outcome::Removed);
+        assert_eq!(Outcome::new(1, 0, 0), Outcome::Failed);
+        assert_eq!(Outcome::new(1, 0, 1), Outcome::PartlyFailed);
+        assert_eq!(Outcome::new(1, 1, 0), Outcome::PartlyFailed);
+    }
+
+    #[test]
+    fn flags_not_given_are_left_to_the_config_files() {
+        let config = config(&[]);
+        assert_eq!(config.delete, None);
+        assert_eq!(config.trash, None);
+        assert_eq!(config.deep, None);
+    }
+
+    #[test]
+    fn negations_turn_options_off() {
+        let config = config(&[
+            "--no-delete",
+            "--no-trash",
+            "--no-deep",
+            "--no-respect-ignores",
+        ]);
+        assert_eq!(config.delete, Some(false));
+        assert_eq!(config.trash, Some(false));
+        assert_eq!(config.deep, Some(false));
+        assert_eq!(config.respect_ignores, Some(false));
+    }
+
+    #[test]
+    fn the_last_of_a_flag_and_its_negation_wins() {
+        assert_eq!(config(&["--deep", "--no-deep"]).deep, Some(false));
+        assert_eq!(config(&["--no-deep", "--deep"]).deep, Some(true));
+        assert_eq!(config(&["--no-delete", "--delete"]).delete, Some(true));
+    }
+
+    #[test]
+    fn one_kind_of_removal_turns_off_the_others() {
+        let trash = config(&["--trash"]);
+        assert_eq!((trash.delete, trash.trash), (Some(false), Some(true)));
+        let delete = config(&["--delete"]);
+        assert_eq!((delete.delete, delete.trash), (Some(true), Some(false)));
+        let check = config(&["--check"]);
+        assert_eq!((check.delete, check.trash), (Some(false), Some(false)));
+    }
+
+    #[test]
+    fn plan_uses_scan_options_from_before_and_after_it() {
+        let cli = Cli::try_parse_from([
+            "python-sweep",
+            "--min-size",
+            "1GB",
+            "--deep",
+            "plan",
+            "--no-deep",
+            "--out",
+            "plan.json",
+        ])
+        .unwrap();
+        let Some(Commands::Plan { scan, .. }) = &cli.command else {
+            panic!("expected the plan command, got {:?}", cli.command);
+        };
+        let config = cli.plan_config(scan);
+        assert_eq!(config.min_size, Some("1000000000".to_string()));
+        assert_eq!(config.deep, Some(false));
     }
 }
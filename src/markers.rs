@@ -0,0 +1,67 @@
+/// Files that mark the root of a Python project, `*` matches any run of characters
+pub const DEFAULT_MARKERS: &[&str] = &[
+    "pyproject.toml",
+    "setup.py",
+    "setup.cfg",
+    "requirements*.txt",
+    "Pipfile",
+    "tox.ini",
+    "noxfile.py",
+    "environment.yml",
+];
+
+/// simple glob matching that only understands `*`
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == name,
+        Some((prefix, rest)) => {
+            let Some(name) = name.strip_prefix(prefix) else {
+                return false;
+            };
+            if rest.is_empty() {
+                return true;
+            }
+            (0..=name.len())
+                .filter(|idx| name.is_char_boundary(*idx))
+                .any(|idx| matches(rest, &name[idx..]))
+        }
+    }
+}
+
+/// returns the first marker pattern that matches a filename
+pub fn matching_marker<'a>(markers: &'a [String], file_name: &str) -> Option<&'a str> {
+    markers
+        .iter()
+        .find(|marker| matches(marker, file_name))
+        .map(|marker| marker.as_str())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_names() {
+        assert!(matches("pyproject.toml", "pyproject.toml"));
+        assert!(!matches("pyproject.toml", "pyproject.toml.bak"));
+        assert!(!matches("Pipfile", "Pipfile.lock"));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches("requirements*.txt", "requirements.txt"));
+        assert!(matches("requirements*.txt", "requirements-dev.txt"));
+        assert!(!matches("requirements*.txt", "requirements-dev.in"));
+        assert!(!matches("requirements*.txt", "dev-requirements.txt"));
+        assert!(matches("*.py", "setup.py"));
+        assert!(matches("a*b*c", "a-b-b-c"));
+        assert!(matches("é*", "éa"));
+    }
+
+    #[test]
+    fn matching_marker_returns_the_first_match() {
+        let markers = ["setup.*", "setup.py"].map(String::from);
+        assert_eq!(matching_marker(&markers, "setup.py"), Some("setup.*"));
+        assert_eq!(matching_marker(&markers, "README.md"), None);
+    }
+}
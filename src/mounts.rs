@@ -0,0 +1,121 @@
+//! Finding the mount points of pseudo filesystems, which never hold environments and can be huge
+//! or slow to walk, like `/proc` or a container's overlay layers
+
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Where Linux lists what's mounted, as this process sees it
+const MOUNTINFO: &str = "/proc/self/mountinfo";
+
+/// Filesystem types the scanner doesn't walk into unless it's asked to
+pub const PSEUDO_FILESYSTEMS: &[&str] = &[
+    "autofs",
+    "binfmt_misc",
+    "bpf",
+    "cgroup",
+    "cgroup2",
+    "configfs",
+    "debugfs",
+    "devpts",
+    "devtmpfs",
+    "fusectl",
+    "hugetlbfs",
+    "mqueue",
+    "overlay",
+    "proc",
+    "pstore",
+    "securityfs",
+    "sysfs",
+    "tracefs",
+];
+
+/// undoes the octal escapes mountinfo uses for spaces and other awkward characters, eg `\040`
+fn unescape(field: &str) -> String {
+    let mut bytes = vec![];
+    let mut rest = field.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let escaped = (byte == b'\\')
+            .then(|| tail.get(..3))
+            .flatten()
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u8::from_str_radix(digits, 8).ok());
+        match escaped {
+            Some(escaped) => {
+                bytes.push(escaped);
+                rest = &tail[3..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    String::from_utf8_lossy(&bytes).to_string()
+}
+
+/// the mount point and filesystem type from a line of mountinfo, which looks like
+/// `36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue`
+fn parse_line(line: &str) -> Option<(PathBuf, String)> {
+    let (mount, filesystem) = line.split_once(" - ")?;
+    let mount_point = mount.split(' ').nth(4)?;
+    let filesystem_type = filesystem.split(' ').next()?;
+    Some((
+        PathBuf::from(unescape(mount_point)),
+        filesystem_type.to_string(),
+    ))
+}
+
+/// where pseudo filesystems are mounted. Empty on systems without `/proc/self/mountinfo`.
+pub fn pseudo_mount_points() -> HashSet<PathBuf> {
+    let Ok(mountinfo) = std::fs::read_to_string(MOUNTINFO) else {
+        return HashSet::new();
+    };
+    mountinfo
+        .lines()
+        .filter_map(parse_line)
+        .filter(|(_, filesystem_type)| PSEUDO_FILESYSTEMS.contains(&filesystem_type.as_str()))
+        .map(|(mount_point, _)| mount_point)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unescapes_octal() {
+        assert_eq!(unescape("/mnt/my\\040disk"), "/mnt/my disk");
+        assert_eq!(unescape("/mnt/a\\011b\\134c"), "/mnt/a\tb\\c");
+        // not an escape, so left alone
+        assert_eq!(unescape("/mnt/a\\9"), "/mnt/a\\9");
+        assert_eq!(unescape("/mnt/end\\"), "/mnt/end\\");
+    }
+
+    #[test]
+    fn parses_mountinfo_lines() {
+        assert_eq!(
+            parse_line(
+                "36 35 98:0 /mnt1 /mnt2 rw,noatime master:1 - ext3 /dev/root rw,errors=continue"
+            ),
+            Some((PathBuf::from("/mnt2"), "ext3".to_string()))
+        );
+        assert_eq!(
+            parse_line(
+                "22 28 0:21 / /proc rw,nosuid,nodev,noexec,relatime shared:12 - proc proc rw"
+            ),
+            Some((PathBuf::from("/proc"), "proc".to_string()))
+        );
+        // no optional fields, and a space in the mount point
+        assert_eq!(
+            parse_line("40 28 0:35 / /media/my\\040disk rw - vfat /dev/sdb1 rw"),
+            Some((PathBuf::from("/media/my disk"), "vfat".to_string()))
+        );
+    }
+
+    #[test]
+    fn skips_lines_it_cant_parse() {
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line("36 35 98:0 /mnt1 /mnt2 rw"), None);
+        assert_eq!(parse_line("36 35 - ext3 /dev/root rw"), None);
+    }
+}
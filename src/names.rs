@@ -0,0 +1,48 @@
+//! The names tools like poetry and pipenv give the environments they keep outside projects: the
+//! project's name with awkward characters replaced, then a short hash of the project's path so
+//! projects with the same name get different environments.
+
+use base64::Engine;
+use sha2::{Digest, Sha256};
+
+/// The longest project name poetry and pipenv use in environment names
+pub const MAX_NAME_LENGTH: usize = 42;
+
+/// replaces the characters in `awkward` with `_`, and cuts the name down to [MAX_NAME_LENGTH]
+pub fn sanitize(name: &str, awkward: &[char]) -> String {
+    name.chars()
+        .map(|c| match awkward.contains(&c) {
+            true => '_',
+            false => c,
+        })
+        .take(MAX_NAME_LENGTH)
+        .collect()
+}
+
+/// the first 8 characters of the url-safe base64 of the SHA-256 of `text`. Poetry encodes the
+/// whole digest and pipenv only its first 6 bytes, but 6 bytes is exactly 8 characters of base64,
+/// so they come out the same.
+pub fn short_hash(text: &str) -> String {
+    let digest = Sha256::digest(text.as_bytes());
+    base64::engine::general_purpose::URL_SAFE.encode(&digest[..6])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn short_hash_is_the_start_of_the_whole_digest() {
+        let digest = Sha256::digest(b"/tmp/rvp/app");
+        let whole = base64::engine::general_purpose::URL_SAFE.encode(digest);
+        assert_eq!(short_hash("/tmp/rvp/app"), whole[..8]);
+        assert_eq!(short_hash("/tmp/rvp/app"), "_JVJ8JwP");
+    }
+
+    #[test]
+    fn sanitize_replaces_and_truncates() {
+        assert_eq!(sanitize("my proj!", &[' ', '!']), "my_proj_");
+        assert_eq!(sanitize("my proj!", &[]), "my proj!");
+        assert_eq!(sanitize(&"a".repeat(50), &[]), "a".repeat(42));
+    }
+}
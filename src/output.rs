@@ -0,0 +1,298 @@
+use std::io::Write;
+
+use serde::Serialize;
+
+use crate::age::format_date;
+use crate::size::human_size;
+use crate::{Environment, Errors};
+
+/// How to print what we found
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable lines
+    #[default]
+    Text,
+    /// A single JSON document with every environment and the totals, written at the end
+    Json,
+    /// One JSON object per environment, written as each one is found
+    Ndjson,
+    /// One CSV row per environment, written as each one is found
+    Csv,
+}
+
+/// What happened to an environment, or what we're doing to all of them
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Action {
+    #[default]
+    Found,
+    Deleted,
+    Trashed,
+    /// A keep marker said to leave it alone
+    Kept,
+}
+
+impl Action {
+    fn verb(&self) -> &'static str {
+        match self {
+            Action::Found => "Found",
+            Action::Deleted => "Deleted",
+            Action::Trashed => "Trashed",
+            Action::Kept => "Kept",
+        }
+    }
+}
+
+/// The machine-readable form of an [Environment]
+#[derive(Debug, Serialize)]
+pub struct Record {
+    pub path: String,
+    pub size: u64,
+    pub human_size: String,
+    pub apparent_size: u64,
+    pub freed_size: u64,
+    pub kind: String,
+    pub project: Option<String>,
+    pub marker: Option<String>,
+    pub python_version: Option<String>,
+    pub creator: Option<String>,
+    /// RFC 3339 timestamp
+    pub last_used: Option<String>,
+    /// Who was using it, in which case it wasn't removed
+    pub in_use: Option<String>,
+    /// The keep marker that said to leave it alone
+    pub kept_by: Option<String>,
+    /// Its project doesn't exist any more
+    pub orphaned: bool,
+    /// Whether it's the one its tool uses for the project, if the tool can have several
+    pub active: Option<bool>,
+    pub deleted: bool,
+    pub trashed: bool,
+}
+
+impl Record {
+    pub fn new(environment: &Environment, action: Action) -> Self {
+        Self {
+            path: environment.path.display().to_string(),
+            size: environment.size,
+            human_size: human_size(environment.size),
+            apparent_size: environment.usage.apparent,
+            freed_size: environment.usage.freed,
+            kind: environment.kind.to_string(),
+            project: environment
+                .project
+                .as_ref()
+                .map(|project| project.display().to_string()),
+            marker: environment.marker.clone(),
+            python_version: environment.cfg.as_ref().and_then(|cfg| cfg.version.clone()),
+            creator: environment.cfg.as_ref().and_then(|cfg| cfg.creator.clone()),
+            last_used: environment
+                .last_used
+                .map(|time| humantime::format_rfc3339_seconds(time).to_string()),
+            in_use: environment.in_use.clone(),
+            kept_by: environment
+                .kept_by
+                .as_ref()
+                .map(|kept_by| kept_by.display().to_string()),
+            orphaned: environment.orphaned,
+            active: environment.active,
+            deleted: action == Action::Deleted,
+            trashed: action == Action::Trashed,
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+struct Document {
+    environments: Vec<Record>,
+    total_size: u64,
+    total_human_size: String,
+    total_freed_size: u64,
+    deleted_size: u64,
+    deleted_human_size: String,
+    trashed_size: u64,
+    trashed_human_size: String,
+    failures: Vec<String>,
+}
+
+/// Writes environments to stdout in the chosen format, and the totals at the end
+pub struct Reporter {
+    format: OutputFormat,
+    records: Vec<Record>,
+    csv: Option<csv::Writer<std::io::Stdout>>,
+    total_size: u64,
+    total_freed_size: u64,
+    deleted_size: u64,
+    trashed_size: u64,
+    kept: usize,
+    failures: Vec<String>,
+}
+
+impl Reporter {
+    pub fn new(format: OutputFormat) -> Self {
+        let csv = match format {
+            OutputFormat::Csv => Some(csv::Writer::from_writer(std::io::stdout())),
+            _ => None,
+        };
+        Self {
+            format,
+            records: vec![],
+            csv,
+            total_size: 0,
+            total_freed_size: 0,
+            deleted_size: 0,
+            trashed_size: 0,
+            kept: 0,
+            failures: vec![],
+        }
+    }
+
+    /// a short description of where the environment came from, for humans
+    pub fn details(environment: &Environment) -> String {
+        [
+            Some(environment.kind.to_string()),
+            environment.cfg.as_ref().map(|cfg| cfg.to_string()),
+            environment
+                .marker
+                .as_ref()
+                .map(|marker| format!("found via {}", marker)),
+            environment
+                .last_used
+                .map(|time| format!("last used {}", format_date(time))),
+            environment
+                .in_use
+                .as_ref()
+                .map(|who| format!("in use by {}", who)),
+            environment
+                .kept_by
+                .as_ref()
+                .map(|kept_by| format!("kept by {}", kept_by.display())),
+            environment
+                .project
+                .as_ref()
+                .filter(|_| environment.orphaned)
+                .map(|project| format!("orphaned, {} is gone", project.display())),
+            environment.active.map(|active| match active {
+                true => "active".to_string(),
+                false => "inactive".to_string(),
+            }),
+        ]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join(", ")
+    }
+
+    /// the size, and how much deleting it would free if that's different
+    pub fn human_sizes(environment: &Environment) -> String {
+        match environment.usage.freed == environment.size {
+            true => human_size(environment.size),
+            false => format!(
+                "{}, {} freed if deleted",
+                human_size(environment.size),
+                human_size(environment.usage.freed)
+            ),
+        }
+    }
+
+    /// report an environment, and what we did with it
+    pub fn report(&mut self, environment: &Environment, action: Action) {
+        if action != Action::Kept {
+            self.total_size += environment.size;
+            self.total_freed_size += environment.usage.freed;
+        }
+        match action {
+            Action::Found => {}
+            Action::Kept => self.kept += 1,
+            Action::Deleted => self.deleted_size += environment.usage.freed,
+            Action::Trashed => self.trashed_size += environment.size,
+        }
+        let record = Record::new(environment, action);
+        match self.format {
+            OutputFormat::Text => {
+                println!(
+                    "{} {:?} ({}) [{}]",
+                    action.verb(),
+                    environment.path,
+                    Self::human_sizes(environment),
+                    Self::details(environment)
+                );
+            }
+            OutputFormat::Json => self.records.push(record),
+            OutputFormat::Ndjson => {
+                let mut stdout = std::io::stdout().lock();
+                if let Err(err) = serde_json::to_writer(&mut stdout, &record)
+                    .map_err(std::io::Error::from)
+                    .and_then(|_| writeln!(stdout))
+                {
+                    eprintln!("Failed to write output: {:?}", err);
+                }
+            }
+            OutputFormat::Csv => {
+                if let Some(writer) = self.csv.as_mut() {
+                    if let Err(err) = writer.serialize(&record).and_then(|_| Ok(writer.flush()?)) {
+                        eprintln!("Failed to write output: {:?}", err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// report something that went wrong, which carries on but makes the whole run a failure
+    pub fn failed(&mut self, err: Errors) {
+        eprintln!("Error: {}", err);
+        self.failures.push(err.to_string());
+    }
+
+    /// write out anything that was waiting for the end of the scan, returning how many things failed
+    pub fn finish(self, action: Action) -> usize {
+        match action {
+            Action::Deleted => {
+                eprintln!("Deleted {} of virtualenvs", human_size(self.deleted_size))
+            }
+            Action::Trashed => eprintln!(
+                "Moved {} of virtualenvs to the trash",
+                human_size(self.trashed_size)
+            ),
+            Action::Found if self.total_freed_size != self.total_size => eprintln!(
+                "Found {} of virtualenvs, deleting them would free {}",
+                human_size(self.total_size),
+                human_size(self.total_freed_size)
+            ),
+            Action::Found | Action::Kept => {
+                eprintln!("Found {} of virtualenvs", human_size(self.total_size))
+            }
+        }
+        if self.kept > 0 {
+            eprintln!(
+                "Kept {} virtualenv{} because of keep markers",
+                self.kept,
+                if self.kept == 1 { "" } else { "s" }
+            );
+        }
+        let failed = self.failures.len();
+        if failed > 0 {
+            eprintln!("{} problem{}:", failed, if failed == 1 { "" } else { "s" });
+            for failure in self.failures.iter() {
+                eprintln!("  {}", failure);
+            }
+        }
+        if self.format == OutputFormat::Json {
+            let document = Document {
+                environments: self.records,
+                total_size: self.total_size,
+                total_human_size: human_size(self.total_size),
+                total_freed_size: self.total_freed_size,
+                deleted_size: self.deleted_size,
+                deleted_human_size: human_size(self.deleted_size),
+                trashed_size: self.trashed_size,
+                trashed_human_size: human_size(self.trashed_size),
+                failures: self.failures,
+            };
+            match serde_json::to_string_pretty(&document) {
+                Ok(output) => println!("{}", output),
+                Err(err) => eprintln!("Failed to write output: {:?}", err),
+            }
+        }
+        failed
+    }
+}
@@ -0,0 +1,158 @@
+//! Pipenv keeps environments in `$WORKON_HOME`, or `~/.local/share/virtualenvs`, named after the
+//! project's directory and a hash of its Pipfile's path, eg `my-project-AbCd_f12`. Each one has a
+//! `.project` file pointing back at the project, so environments whose project has been deleted
+//! can be found by looking through them.
+
+use std::path::{Path, PathBuf};
+
+use crate::env_var;
+use crate::names::{sanitize, short_hash};
+use crate::pyvenv::{environments_for, is_virtualenv, orphaned_in};
+
+/// Where pipenv keeps environments that aren't in their projects
+pub fn virtualenvs_path() -> Option<PathBuf> {
+    if let Some(workon_home) = env_var("WORKON_HOME") {
+        return Some(workon_home);
+    }
+    Some(
+        PathBuf::from(std::env::var_os("HOME")?)
+            .join(".local")
+            .join("share")
+            .join("virtualenvs"),
+    )
+}
+
+/// whether an environment variable pipenv reads is set to something true
+fn env_flag(name: &str) -> Option<bool> {
+    let value = std::env::var(name).ok()?;
+    Some(!matches!(
+        value.to_lowercase().as_str(),
+        "" | "0" | "false" | "no" | "off"
+    ))
+}
+
+/// pipenv's name for a project's environment, unless `PIPENV_CUSTOM_VENV_NAME` gives one, see
+/// [name_for]
+pub fn env_name(project: &Path) -> Option<String> {
+    if let Some(name) = env_var("PIPENV_CUSTOM_VENV_NAME") {
+        return Some(name.to_string_lossy().to_string());
+    }
+    name_for(&project.canonicalize().ok()?)
+}
+
+/// the name pipenv derives for a project's environment: the project directory's name with awkward
+/// characters replaced, and a short hash of the Pipfile's path. `project` should already have its
+/// symlinks resolved.
+fn name_for(project: &Path) -> Option<String> {
+    let name = sanitize(
+        &project.file_name()?.to_string_lossy(),
+        &[
+            ' ', '&', '$', '`', '!', '*', '@', '"', '(', ')', '[', ']', '\\', '\r', '\n', '\t',
+        ],
+    );
+    let pipfile = project.join("Pipfile").to_string_lossy().to_string();
+    // os.path.normcase, which only does anything on windows
+    let pipfile = match cfg!(windows) {
+        true => pipfile.to_lowercase(),
+        false => pipfile,
+    };
+    Some(format!("{}-{}", name, short_hash(&pipfile)))
+}
+
+/// whether an environment has the name pipenv gives `project`'s environment
+fn named_for(environment: &Path, project: &Path) -> bool {
+    environment
+        .file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name_for(project).as_deref() == Some(name))
+}
+
+/// whether an environment in [virtualenvs_path] is one pipenv made for `project`, so tools that
+/// share the directory, like virtualenvwrapper, can leave it to pipenv. `project` doesn't have to
+/// exist any more.
+pub fn made_for(environment: &Path, project: &Path) -> bool {
+    environment.parent() == virtualenvs_path().as_deref() && named_for(environment, project)
+}
+
+/// the environments pipenv has for a project, worked out the way pipenv does: `.venv` in the
+/// project if it's there or `PIPENV_VENV_IN_PROJECT` is set, otherwise the one named by
+/// [env_name]. Environments in [virtualenvs_path] whose `.project` file points at the project are
+/// included too, in case they were made with other settings.
+pub fn locate(project: &Path) -> Vec<PathBuf> {
+    let in_project = project.join(".venv");
+    let in_project_setting = env_flag("PIPENV_VENV_IN_PROJECT");
+    if in_project_setting != Some(false) && in_project.is_dir() {
+        return vec![in_project];
+    }
+    if in_project_setting == Some(true) {
+        return vec![];
+    }
+    let Some(virtualenvs_path) = virtualenvs_path() else {
+        return vec![];
+    };
+    let mut environments = env_name(project)
+        .map(|name| virtualenvs_path.join(name))
+        .filter(|path| is_virtualenv(path))
+        .into_iter()
+        .collect::<Vec<_>>();
+    for path in environments_for(&virtualenvs_path, project) {
+        if !environments.contains(&path) {
+            environments.push(path);
+        }
+    }
+    environments
+}
+
+/// the environments in [virtualenvs_path] whose `.project` file points at a directory that
+/// doesn't exist any more, with where the project was
+pub fn orphaned_environments() -> Vec<(PathBuf, PathBuf)> {
+    virtualenvs_path()
+        .map(|path| orphaned_in(&path))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn name_for_matches_pipenv() {
+        assert_eq!(
+            name_for(Path::new("/tmp/rv/proj")).as_deref(),
+            Some("proj-Atj6tVMF")
+        );
+        assert_eq!(
+            name_for(Path::new("/home/user/src/proj1")).as_deref(),
+            Some("proj1--a55DRHT")
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn name_for_sanitizes_and_truncates_the_directory_name() {
+        let name = name_for(Path::new("/tmp/rv/my proj (old)")).unwrap();
+        assert!(name.starts_with("my_proj__old_-"), "{}", name);
+        let name = name_for(&Path::new("/tmp/rv").join("a".repeat(50))).unwrap();
+        assert_eq!(name.len(), 42 + 1 + 8);
+        assert!(name.starts_with(&format!("{}-", "a".repeat(42))));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn named_for_checks_the_whole_name() {
+        let project = Path::new("/tmp/rv/proj");
+        assert!(named_for(Path::new("/venvs/proj-Atj6tVMF"), project));
+        assert!(!named_for(Path::new("/venvs/proj-Atj6tVMG"), project));
+        assert!(!named_for(Path::new("/venvs/proj"), project));
+        assert!(!named_for(
+            Path::new("/venvs/proj-Atj6tVMF"),
+            Path::new("/tmp/rv/other")
+        ));
+    }
+
+    #[test]
+    fn name_for_needs_a_directory_name() {
+        assert_eq!(name_for(Path::new("/")), None);
+    }
+}
@@ -0,0 +1,198 @@
+//! Two-phase sweeping: write the environments a scan found to a plan file that a human can edit,
+//! then later delete exactly what's left in it, as long as nothing changed in between.
+
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use serde::{Deserialize, Serialize};
+
+use crate::detectors::DETECTOR_NAMES;
+use crate::pyvenv::PYVENV_CFG;
+use crate::size::DiskUsage;
+use crate::{Environment, Errors};
+
+/// Bump this if the plan format changes in a way older versions can't read
+pub const PLAN_VERSION: u32 = 1;
+
+/// Enough about a directory to tell if it's been replaced or changed since it was planned
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Fingerprint {
+    pub device: Option<u64>,
+    pub inode: Option<u64>,
+    /// Modification time of the directory, in nanoseconds since the epoch
+    pub modified: Option<u128>,
+    /// Modification time of its `pyvenv.cfg`, which changes if the environment is recreated
+    pub pyvenv_cfg_modified: Option<u128>,
+}
+
+fn modified_nanos(path: &Path) -> Option<u128> {
+    let modified = path.symlink_metadata().ok()?.modified().ok()?;
+    Some(modified.duration_since(UNIX_EPOCH).ok()?.as_nanos())
+}
+
+impl Fingerprint {
+    pub fn of(path: &Path) -> std::io::Result<Self> {
+        #[cfg(unix)]
+        let (device, inode) = {
+            use std::os::unix::fs::MetadataExt;
+            let metadata = path.symlink_metadata()?;
+            (Some(metadata.dev()), Some(metadata.ino()))
+        };
+        #[cfg(not(unix))]
+        let (device, inode) = {
+            path.symlink_metadata()?;
+            (None, None)
+        };
+        Ok(Self {
+            device,
+            inode,
+            modified: modified_nanos(path),
+            pyvenv_cfg_modified: modified_nanos(&path.join(PYVENV_CFG)),
+        })
+    }
+}
+
+/// One environment in a plan
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PlanEntry {
+    pub path: PathBuf,
+    pub kind: String,
+    pub project: Option<PathBuf>,
+    /// The scan root it was found under, which it mustn't contain when it's removed
+    #[serde(default)]
+    pub root: Option<PathBuf>,
+    pub size: u64,
+    pub usage: DiskUsage,
+    pub fingerprint: Fingerprint,
+}
+
+impl PlanEntry {
+    /// Paths are stored absolute, so the plan can be applied from another directory
+    pub fn new(environment: &Environment) -> Result<Self, Errors> {
+        let fingerprint = Fingerprint::of(&environment.path)
+            .map_err(|err| Errors::io("fingerprint", &environment.path, err))?;
+        let absolute = |path: &Path| {
+            std::path::absolute(path)
+                .map_err(|err| Errors::io("get the absolute path of", path, err))
+        };
+        Ok(Self {
+            path: absolute(&environment.path)?,
+            kind: environment.kind.to_string(),
+            project: environment.project.as_deref().map(absolute).transpose()?,
+            root: environment.root.as_deref().map(absolute).transpose()?,
+            size: environment.size,
+            usage: environment.usage,
+            fingerprint,
+        })
+    }
+
+    /// checks the environment is still the one that was planned
+    pub fn verify(&self) -> Result<(), Errors> {
+        let current = Fingerprint::of(&self.path).map_err(|err| Errors::InvalidVenv {
+            path: self.path.clone(),
+            reason: format!("couldn't fingerprint it: {}", err),
+        })?;
+        if current != self.fingerprint {
+            return Err(Errors::InvalidVenv {
+                path: self.path.clone(),
+                reason: "it has changed since the plan was made".to_string(),
+            });
+        }
+        Ok(())
+    }
+
+    /// turns the entry back into an environment to hand to the delete functions
+    pub fn environment(&self) -> Environment {
+        let kind = DETECTOR_NAMES
+            .iter()
+            .find(|name| **name == self.kind)
+            .copied()
+            .unwrap_or("plan");
+        let mut environment = Environment::new(self.path.clone(), kind);
+        environment.project = self.project.clone();
+        environment.root = self.root.clone();
+        environment.size = self.size;
+        environment.usage = self.usage;
+        environment
+    }
+}
+
+/// A list of environments to remove later
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Plan {
+    pub version: u32,
+    /// RFC 3339 timestamp of when the plan was made
+    pub created: String,
+    pub environments: Vec<PlanEntry>,
+}
+
+impl Default for Plan {
+    fn default() -> Self {
+        Self {
+            version: PLAN_VERSION,
+            created: humantime::format_rfc3339_seconds(std::time::SystemTime::now()).to_string(),
+            environments: vec![],
+        }
+    }
+}
+
+impl Plan {
+    pub fn load(path: &Path) -> Result<Self, Errors> {
+        let contents =
+            std::fs::read_to_string(path).map_err(|err| Errors::io("read plan", path, err))?;
+        let plan: Self = serde_json::from_str(&contents).map_err(|err| {
+            Errors::Other(format!("Failed to parse plan {}: {}", path.display(), err))
+        })?;
+        if plan.version != PLAN_VERSION {
+            return Err(Errors::Other(format!(
+                "Plan {} is version {}, this version of python-sweep only understands version {}",
+                path.display(),
+                plan.version,
+                PLAN_VERSION
+            )));
+        }
+        Ok(plan)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), Errors> {
+        let contents = serde_json::to_string_pretty(self)
+            .map_err(|err| Errors::Other(format!("Failed to serialize plan: {}", err)))?;
+        std::fs::write(path, contents + "\n").map_err(|err| Errors::io("write plan", path, err))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn entries_have_absolute_paths() {
+        let mut environment = Environment::new(PathBuf::from("./src"), "pyvenv");
+        environment.project = Some(PathBuf::from("."));
+        environment.root = Some(PathBuf::from("."));
+        let entry = PlanEntry::new(&environment).unwrap();
+        let current_dir = std::env::current_dir().unwrap();
+        assert_eq!(entry.path, current_dir.join("src"));
+        assert_eq!(entry.project.as_deref(), Some(current_dir.as_path()));
+        assert_eq!(entry.root.as_deref(), Some(current_dir.as_path()));
+    }
+
+    #[test]
+    fn verify_refuses_a_recreated_environment() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join(".venv");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join(PYVENV_CFG), "home = /usr/bin\n").unwrap();
+        let entry = PlanEntry::new(&Environment::new(path.clone(), "pyvenv")).unwrap();
+        entry.verify().unwrap();
+
+        // keep the old one around, so the new directory can't reuse its inode
+        std::fs::rename(&path, dir.path().join("old")).unwrap();
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join(PYVENV_CFG), "home = /usr/bin\n").unwrap();
+        assert!(matches!(
+            entry.verify(),
+            Err(Errors::InvalidVenv { path: refused, .. }) if refused == path
+        ));
+    }
+}
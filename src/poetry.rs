@@ -0,0 +1,545 @@
+//! Poetry keeps most environments together in one directory, named after the project and a hash
+//! of the project's path, eg `~/.cache/pypoetry/virtualenvs/my-project-AbCd_f12-py3.12`. The hash
+//! can't be reversed, but an environment usually records where its project was installed from,
+//! so we can check which recorded path hashes to the name.
+//!
+//! Working out where a project's environment is this way, from poetry's settings, saves running
+//! `poetry env info` for every project, which takes about half a second each time.
+
+use std::path::{Path, PathBuf};
+
+use crate::env_var;
+use crate::names::{sanitize, short_hash};
+use crate::pyvenv::{is_virtualenv, site_packages};
+
+/// Where poetry's own `config.toml` is
+pub fn config_dir() -> Option<PathBuf> {
+    if let Some(config_dir) = env_var("POETRY_CONFIG_DIR") {
+        return Some(config_dir);
+    }
+    let home = env_var("HOME");
+    if cfg!(target_os = "macos") {
+        return Some(
+            home?
+                .join("Library")
+                .join("Application Support")
+                .join("pypoetry"),
+        );
+    }
+    if cfg!(windows) {
+        return Some(env_var("APPDATA")?.join("pypoetry"));
+    }
+    let config_home = env_var("XDG_CONFIG_HOME").or_else(|| Some(home?.join(".config")))?;
+    Some(config_home.join("pypoetry"))
+}
+
+/// Poetry's default cache directory, where `virtualenvs` lives unless it's configured otherwise
+fn default_cache_dir() -> Option<PathBuf> {
+    let home = env_var("HOME");
+    if cfg!(target_os = "macos") {
+        return Some(home?.join("Library").join("Caches").join("pypoetry"));
+    }
+    if cfg!(windows) {
+        return Some(env_var("LOCALAPPDATA")?.join("pypoetry").join("Cache"));
+    }
+    let cache_home = env_var("XDG_CACHE_HOME").or_else(|| Some(home?.join(".cache")))?;
+    Some(cache_home.join("pypoetry"))
+}
+
+/// expands a leading `~` to the home directory, like the `expanduser()` poetry does on paths from
+/// its settings
+fn expand_home(path: &Path) -> PathBuf {
+    match (path.strip_prefix("~"), env_var("HOME")) {
+        (Ok(rest), Some(home)) => home.join(rest),
+        _ => path.to_path_buf(),
+    }
+}
+
+/// parses the booleans poetry accepts in environment variables
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "1" | "yes" | "on" => Some(true),
+        "false" | "0" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// The poetry settings that decide where environments go
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Settings {
+    /// `virtualenvs.in-project`, unset means use `.venv` if it's there
+    pub in_project: Option<bool>,
+    /// `virtualenvs.path`, which can refer to `{cache-dir}`
+    pub virtualenvs_path: Option<String>,
+    /// `cache-dir`
+    pub cache_dir: Option<PathBuf>,
+}
+
+impl Settings {
+    /// reads the settings from a `config.toml` or `poetry.toml`, missing files have no settings
+    pub fn from_file(path: &Path) -> Self {
+        let Some(config) = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|contents| contents.parse::<toml::Table>().ok())
+        else {
+            return Self::default();
+        };
+        let virtualenvs = config.get("virtualenvs");
+        Self {
+            in_project: virtualenvs
+                .and_then(|virtualenvs| virtualenvs.get("in-project"))
+                .and_then(|in_project| in_project.as_bool()),
+            virtualenvs_path: virtualenvs
+                .and_then(|virtualenvs| virtualenvs.get("path"))
+                .and_then(|path| path.as_str())
+                .map(|path| path.to_string()),
+            cache_dir: config
+                .get("cache-dir")
+                .and_then(|cache_dir| cache_dir.as_str())
+                .map(PathBuf::from),
+        }
+    }
+
+    /// the `POETRY_*` environment variables, which beat any config file
+    pub fn from_env() -> Self {
+        Self {
+            in_project: std::env::var("POETRY_VIRTUALENVS_IN_PROJECT")
+                .ok()
+                .and_then(|value| parse_bool(&value)),
+            virtualenvs_path: env_var("POETRY_VIRTUALENVS_PATH")
+                .map(|path| path.to_string_lossy().to_string()),
+            cache_dir: env_var("POETRY_CACHE_DIR"),
+        }
+    }
+
+    /// these settings, with anything set in `over` replacing them
+    fn merge(self, over: Self) -> Self {
+        Self {
+            in_project: over.in_project.or(self.in_project),
+            virtualenvs_path: over.virtualenvs_path.or(self.virtualenvs_path),
+            cache_dir: over.cache_dir.or(self.cache_dir),
+        }
+    }
+
+    /// the settings in poetry's own `config.toml`
+    fn from_config_dir() -> Self {
+        config_dir()
+            .map(|config_dir| Self::from_file(&config_dir.join("config.toml")))
+            .unwrap_or_default()
+    }
+
+    /// the user's settings, from poetry's `config.toml` and the environment
+    pub fn global() -> Self {
+        Self::from_config_dir().merge(Self::from_env())
+    }
+
+    /// the settings for a project, which can have its own `poetry.toml`
+    pub fn for_project(project: &Path) -> Self {
+        Self::from_config_dir()
+            .merge(Self::from_file(&project.join("poetry.toml")))
+            .merge(Self::from_env())
+    }
+
+    /// Where poetry keeps environments that aren't in their projects
+    pub fn virtualenvs_path(&self) -> Option<PathBuf> {
+        let cache_dir = self
+            .cache_dir
+            .as_deref()
+            .map(expand_home)
+            .or_else(default_cache_dir);
+        match &self.virtualenvs_path {
+            Some(path) if path.contains("{cache-dir}") => Some(expand_home(Path::new(
+                &path.replace("{cache-dir}", &cache_dir?.to_string_lossy()),
+            ))),
+            Some(path) => Some(expand_home(Path::new(path))),
+            None => Some(cache_dir?.join("virtualenvs")),
+        }
+    }
+}
+
+/// Where poetry keeps environments that aren't in their projects, going by the user's settings
+pub fn virtualenvs_path() -> Option<PathBuf> {
+    Settings::global().virtualenvs_path()
+}
+
+/// the first 8 characters of the url-safe base64 SHA-256 of a project's path, which poetry puts
+/// in environment names so projects with the same name get different environments
+pub fn path_hash(project: &Path) -> String {
+    let path = project.to_string_lossy();
+    // os.path.normcase, which only does anything on windows
+    let path = match cfg!(windows) {
+        true => path.to_lowercase().replace('/', "\\"),
+        false => path.to_string(),
+    };
+    short_hash(&path)
+}
+
+/// normalises a package name the way packaging does, so `My_Project` becomes `my-project`
+fn canonicalize_name(name: &str) -> String {
+    let mut canonical = String::new();
+    for c in name.to_lowercase().chars() {
+        match c {
+            '-' | '_' | '.' if canonical.ends_with('-') => {}
+            '-' | '_' | '.' => canonical.push('-'),
+            c => canonical.push(c),
+        }
+    }
+    canonical
+}
+
+/// poetry's name for a project's environments, before the `-pyX.Y` on the end. `project` should
+/// already have its symlinks resolved.
+pub fn env_name(name: &str, project: &Path) -> String {
+    let sanitized = sanitize(
+        &canonicalize_name(name),
+        &[' ', '$', '`', '!', '*', '@', '"', '\\', '\r', '\n', '\t'],
+    );
+    format!("{}-{}", sanitized, path_hash(project))
+}
+
+/// the project's name from pyproject.toml, `[project]` for poetry 2 or `[tool.poetry]` before
+fn project_name(project: &Path) -> Option<String> {
+    let pyproject = std::fs::read_to_string(project.join("pyproject.toml"))
+        .ok()?
+        .parse::<toml::Table>()
+        .ok()?;
+    let from_project = pyproject.get("project").and_then(|table| table.get("name"));
+    let from_tool = pyproject
+        .get("tool")
+        .and_then(|tool| tool.get("poetry"))
+        .and_then(|table| table.get("name"));
+    Some(from_project.or(from_tool)?.as_str()?.to_string())
+}
+
+/// the Python version poetry has recorded as active for an environment name, in `envs.toml`
+fn active_version(virtualenvs_path: &Path, env_name: &str) -> Option<String> {
+    let envs = std::fs::read_to_string(virtualenvs_path.join("envs.toml"))
+        .ok()?
+        .parse::<toml::Table>()
+        .ok()?;
+    Some(envs.get(env_name)?.get("minor")?.as_str()?.to_string())
+}
+
+/// every environment poetry has made with this name, one per Python version
+pub fn environments_named(virtualenvs_path: &Path, env_name: &str) -> Vec<PathBuf> {
+    let Ok(entries) = std::fs::read_dir(virtualenvs_path) else {
+        return vec![];
+    };
+    let mut environments = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let dir_name = entry.file_name().to_string_lossy().to_string();
+            parse_env_dir_name(&dir_name).is_some()
+                && dir_name
+                    .strip_prefix(env_name)
+                    .is_some_and(|rest| rest.starts_with("-py"))
+        })
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    environments.sort();
+    environments
+}
+
+/// What we could work out about a project's environments without running poetry
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Located {
+    /// Every environment poetry has made for the project, like `poetry env list --full-path`
+    pub environments: Vec<PathBuf>,
+    /// The one poetry uses, if it's made it
+    pub active: Option<PathBuf>,
+    /// Whether we know which one is active. Only poetry can say when there are several
+    /// environments and none is recorded as active, or we couldn't work out their names.
+    pub known: bool,
+}
+
+/// finds a project's environments and works out which one poetry uses, the same way poetry
+/// does: `.venv` in the project unless that's turned off, otherwise the one named after the
+/// project and the hash of its path, for the Python version recorded in `envs.toml`
+pub fn locate(project: &Path) -> Located {
+    let settings = Settings::for_project(project);
+    let in_project = project.join(".venv");
+    let uses_in_project = settings.in_project != Some(false) && in_project.is_dir();
+    let environments = match (
+        project_name(project),
+        settings.virtualenvs_path(),
+        project.canonicalize(),
+    ) {
+        (Some(name), Some(virtualenvs_path), Ok(project)) => {
+            let env_name = env_name(&name, &project);
+            Some((
+                environments_named(&virtualenvs_path, &env_name),
+                active_version(&virtualenvs_path, &env_name)
+                    .map(|version| virtualenvs_path.join(format!("{}-py{}", env_name, version))),
+            ))
+        }
+        _ => None,
+    };
+    match (uses_in_project, environments) {
+        (true, environments) => Located {
+            environments: [in_project.clone()]
+                .into_iter()
+                .chain(environments.into_iter().flat_map(|(named, _)| named))
+                .collect(),
+            active: Some(in_project),
+            known: true,
+        },
+        // poetry would make a new .venv rather than use any of these
+        (false, Some((environments, _))) if settings.in_project == Some(true) => Located {
+            environments,
+            active: None,
+            known: true,
+        },
+        (false, Some((environments, Some(active)))) => Located {
+            active: environments.contains(&active).then_some(active),
+            environments,
+            known: true,
+        },
+        (false, Some((environments, None))) if environments.len() <= 1 => Located {
+            active: environments.first().cloned(),
+            environments,
+            known: true,
+        },
+        (false, Some((environments, None))) => Located {
+            environments,
+            active: None,
+            known: false,
+        },
+        (false, None) => Located::default(),
+    }
+}
+
+/// splits an environment's directory name into the project name, path hash and Python version,
+/// eg `my-project`, `AbCd_f12` and `3.12`. The hash can have `-` in it, so it's found by length.
+pub fn parse_env_dir_name(dir_name: &str) -> Option<(&str, &str, &str)> {
+    let (rest, version) = dir_name.rsplit_once("-py")?;
+    if !version
+        .split('.')
+        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit()))
+    {
+        return None;
+    }
+    let split = rest.len().checked_sub(8)?;
+    if !rest.is_char_boundary(split) {
+        return None;
+    }
+    let (name, hash) = rest.split_at(split);
+    Some((name.strip_suffix('-')?, hash, version))
+}
+
+/// turns a `file://` URL back into a path
+fn file_url_path(url: &str) -> Option<PathBuf> {
+    let path = url.strip_prefix("file://")?;
+    let mut bytes = vec![];
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        let decoded = (byte == b'%')
+            .then(|| tail.get(..2))
+            .flatten()
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match decoded {
+            Some(decoded) => {
+                bytes.push(decoded);
+                rest = &tail[2..];
+            }
+            None => {
+                bytes.push(byte);
+                rest = tail;
+            }
+        }
+    }
+    Some(PathBuf::from(String::from_utf8_lossy(&bytes).to_string()))
+}
+
+/// the paths an environment says its project was installed from. Editable installs record their
+/// source in `direct_url.json`, and the `.pth` file poetry writes for the project itself lists
+/// its source directories.
+fn recorded_project_paths(environment: &Path) -> Vec<PathBuf> {
+    let mut paths = vec![];
+    for dir in site_packages(environment) {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            let path = entry.path();
+            if path.extension().is_some_and(|ext| ext == "pth") {
+                if let Ok(contents) = std::fs::read_to_string(&path) {
+                    paths.extend(
+                        contents
+                            .lines()
+                            .map(|line| line.trim())
+                            .filter(|line| Path::new(line).is_absolute())
+                            .map(PathBuf::from),
+                    );
+                }
+            } else if path.extension().is_some_and(|ext| ext == "dist-info") {
+                let url = std::fs::read_to_string(path.join("direct_url.json"))
+                    .ok()
+                    .and_then(|contents| serde_json::from_str::<serde_json::Value>(&contents).ok())
+                    .and_then(|direct_url| Some(direct_url.get("url")?.as_str()?.to_string()));
+                paths.extend(url.as_deref().and_then(file_url_path));
+            }
+        }
+    }
+    paths
+}
+
+/// works out which project an environment in [virtualenvs_path] belongs to, by finding a path it
+/// recorded, or one of that path's parents, that hashes to the hash in its name
+pub fn resolve_project(environment: &Path) -> Option<PathBuf> {
+    let dir_name = environment.file_name()?.to_str()?;
+    let (_, hash, _) = parse_env_dir_name(dir_name)?;
+    recorded_project_paths(environment)
+        .iter()
+        .flat_map(|path| path.ancestors())
+        .find(|candidate| path_hash(candidate) == hash)
+        .map(Path::to_path_buf)
+}
+
+/// the environments in [virtualenvs_path] whose project directory doesn't exist any more, with
+/// where the project was. Environments we can't trace back to a project are left out.
+pub fn orphaned_environments() -> Vec<(PathBuf, PathBuf)> {
+    let Some(entries) = virtualenvs_path().and_then(|path| std::fs::read_dir(path).ok()) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_virtualenv(path))
+        .filter_map(|path| {
+            let project = resolve_project(&path)?;
+            (!project.exists()).then_some((path, project))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn path_hash_matches_poetry() {
+        assert_eq!(path_hash(Path::new("/tmp/rvp/app")), "_JVJ8JwP");
+        assert_eq!(path_hash(Path::new("/home/user/src/proj3")), "XkliPJh-");
+    }
+
+    #[test]
+    fn canonicalize_name_normalises_separators() {
+        assert_eq!(canonicalize_name("My_Proj"), "my-proj");
+        assert_eq!(canonicalize_name("a.-_b"), "a-b");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn env_name_matches_poetry() {
+        let project = Path::new("/tmp/rvp/app");
+        assert_eq!(env_name("My_Proj", project), "my-proj-_JVJ8JwP");
+        assert_eq!(env_name("my proj!", project), "my_proj_-_JVJ8JwP");
+        assert_eq!(
+            env_name(&"a".repeat(50), project),
+            format!("{}-_JVJ8JwP", "a".repeat(42))
+        );
+    }
+
+    #[test]
+    fn parse_env_dir_name_splits_off_the_hash_and_version() {
+        assert_eq!(
+            parse_env_dir_name("my-proj-_JVJ8JwP-py3.12"),
+            Some(("my-proj", "_JVJ8JwP", "3.12"))
+        );
+        assert_eq!(
+            parse_env_dir_name("proj3-XkliPJh--py3.9"),
+            Some(("proj3", "XkliPJh-", "3.9"))
+        );
+        assert_eq!(
+            parse_env_dir_name("a-b-c--d-ef-gh-py3.10"),
+            Some(("a-b-c", "-d-ef-gh", "3.10"))
+        );
+    }
+
+    #[test]
+    fn parse_env_dir_name_rejects_other_names() {
+        assert_eq!(parse_env_dir_name("my-proj-_JVJ8JwP"), None);
+        assert_eq!(parse_env_dir_name("my-proj-_JVJ8JwP-py3.x"), None);
+        assert_eq!(parse_env_dir_name("my-proj-_JVJ8JwP-py"), None);
+        assert_eq!(parse_env_dir_name("_JVJ8JwP-py3.12"), None);
+        assert_eq!(parse_env_dir_name("short-py3.12"), None);
+    }
+
+    #[test]
+    fn file_url_path_decodes_escapes() {
+        assert_eq!(
+            file_url_path("file:///tmp/rvp/app"),
+            Some(PathBuf::from("/tmp/rvp/app"))
+        );
+        assert_eq!(
+            file_url_path("file:///tmp/my%20app/%E2%9C%93"),
+            Some(PathBuf::from("/tmp/my app/✓"))
+        );
+        assert_eq!(
+            file_url_path("file:///tmp/100%"),
+            Some(PathBuf::from("/tmp/100%"))
+        );
+        assert_eq!(file_url_path("https://example.com/app"), None);
+    }
+
+    /// makes an environment in `virtualenvs` named for `project`, with its site-packages
+    fn make_environment(virtualenvs: &Path, project: &Path) -> (PathBuf, PathBuf) {
+        let environment = virtualenvs.join(format!("app-{}-py3.12", path_hash(project)));
+        let site_packages = environment
+            .join("lib")
+            .join("python3.12")
+            .join("site-packages");
+        std::fs::create_dir_all(&site_packages).unwrap();
+        (environment, site_packages)
+    }
+
+    #[test]
+    fn resolve_project_finds_the_project_from_a_pth_file() {
+        let root = tempfile::tempdir().unwrap();
+        let project = root.path().join("app");
+        let (environment, site_packages) = make_environment(root.path(), &project);
+        std::fs::write(
+            site_packages.join("app.pth"),
+            format!("{}\n", project.join("src").display()),
+        )
+        .unwrap();
+        // the project doesn't have to exist, that's the point
+        assert_eq!(resolve_project(&environment), Some(project));
+    }
+
+    #[test]
+    fn resolve_project_finds_the_project_from_direct_url_json() {
+        let root = tempfile::tempdir().unwrap();
+        let project = root.path().join("my app");
+        let (environment, site_packages) = make_environment(root.path(), &project);
+        let dist_info = site_packages.join("app-0.1.0.dist-info");
+        std::fs::create_dir_all(&dist_info).unwrap();
+        let url = format!(
+            "file://{}",
+            project.display().to_string().replace(' ', "%20")
+        );
+        std::fs::write(
+            dist_info.join("direct_url.json"),
+            serde_json::json!({ "url": url, "dir_info": { "editable": true } }).to_string(),
+        )
+        .unwrap();
+        assert_eq!(resolve_project(&environment), Some(project));
+    }
+
+    #[test]
+    fn resolve_project_needs_a_path_matching_the_hash() {
+        let root = tempfile::tempdir().unwrap();
+        let project = root.path().join("app");
+        let (environment, site_packages) = make_environment(root.path(), &project);
+        std::fs::write(
+            site_packages.join("other.pth"),
+            format!("{}\n", root.path().join("other").display()),
+        )
+        .unwrap();
+        assert_eq!(resolve_project(&environment), None);
+        assert_eq!(resolve_project(&root.path().join("not-poetry")), None);
+    }
+}
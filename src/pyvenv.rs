@@ -0,0 +1,198 @@
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+
+/// The file that `venv`, `virtualenv` and `uv` all drop in the root of an environment
+pub const PYVENV_CFG: &str = "pyvenv.cfg";
+
+/// What we could pull out of a `pyvenv.cfg` file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PyvenvCfg {
+    /// The interpreter version, eg `3.11.4`
+    pub version: Option<String>,
+    /// The tool that created the environment, eg `venv`, `virtualenv 20.24.0` or `uv 0.4.0`
+    pub creator: Option<String>,
+}
+
+impl PyvenvCfg {
+    /// parse the contents of a `pyvenv.cfg` file, ignoring anything we don't understand
+    pub fn parse(contents: &str) -> Self {
+        let mut version = None;
+        let mut version_info = None;
+        let mut creator = None;
+        let mut has_command = false;
+
+        for line in contents.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim() {
+                "version" => version = Some(value),
+                // virtualenv and uv write "version_info = 3.11.4.final.0"
+                "version_info" => {
+                    version_info = Some(
+                        value
+                            .split('.')
+                            .take_while(|part| part.chars().all(|c| c.is_ascii_digit()))
+                            .collect::<Vec<_>>()
+                            .join("."),
+                    )
+                }
+                "uv" => creator = Some(format!("uv {}", value)),
+                "virtualenv" if creator.is_none() => {
+                    creator = Some(format!("virtualenv {}", value))
+                }
+                "command" => has_command = true,
+                _ => {}
+            }
+        }
+
+        if creator.is_none() && (has_command || version.is_some()) {
+            creator = Some("venv".to_string());
+        }
+
+        Self {
+            version: version.or(version_info).filter(|v| !v.is_empty()),
+            creator,
+        }
+    }
+
+    /// read and parse the `pyvenv.cfg` in a directory
+    pub fn from_dir(path: &Path) -> std::io::Result<Self> {
+        std::fs::read_to_string(path.join(PYVENV_CFG)).map(|contents| Self::parse(&contents))
+    }
+}
+
+impl Display for PyvenvCfg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "python {}, created by {}",
+            self.version.as_deref().unwrap_or("unknown"),
+            self.creator.as_deref().unwrap_or("unknown")
+        )
+    }
+}
+
+/// the interpreter paths we expect to find in a virtualenv, unix then windows
+fn interpreter_paths(path: &Path) -> [PathBuf; 2] {
+    [
+        path.join("bin").join("python"),
+        path.join("Scripts").join("python.exe"),
+    ]
+}
+
+/// the site-packages directories that exist in an environment, `lib/python3.x/site-packages` on
+/// unix (there's usually only one) and `Lib/site-packages` on windows
+pub fn site_packages(path: &Path) -> Vec<PathBuf> {
+    let mut site_packages = vec![path.join("Lib").join("site-packages")];
+    if let Ok(entries) = std::fs::read_dir(path.join("lib")) {
+        site_packages.extend(
+            entries
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path().join("site-packages")),
+        );
+    }
+    site_packages.retain(|dir| dir.is_dir());
+    site_packages
+}
+
+/// where the `.project` file pipenv and virtualenvwrapper put in an environment says its project
+/// is, if there is one
+pub fn project_file(path: &Path) -> Option<PathBuf> {
+    let project = std::fs::read_to_string(path.join(".project")).ok()?;
+    let project = project.trim();
+    (!project.is_empty()).then(|| PathBuf::from(project))
+}
+
+/// the virtualenvs directly inside `dir` that have a [project_file], with the project it names
+pub fn with_project_files(dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut environments = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| is_virtualenv(path))
+        .filter_map(|path| {
+            let project = project_file(&path)?;
+            Some((path, project))
+        })
+        .collect::<Vec<_>>();
+    environments.sort();
+    environments
+}
+
+/// the environments in `dir` whose [project_file] points at `project`
+pub fn environments_for(dir: &Path, project: &Path) -> Vec<PathBuf> {
+    let project = project.canonicalize().unwrap_or(project.to_path_buf());
+    with_project_files(dir)
+        .into_iter()
+        .filter(|(_, recorded)| {
+            *recorded == project || recorded.canonicalize().is_ok_and(|r| r == project)
+        })
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// the environments in `dir` whose [project_file] points at a directory that doesn't exist
+pub fn orphaned_in(dir: &Path) -> Vec<(PathBuf, PathBuf)> {
+    with_project_files(dir)
+        .into_iter()
+        .filter(|(_, project)| !project.exists())
+        .collect()
+}
+
+/// checks if a directory looks like a virtualenv - it has a `pyvenv.cfg` and an interpreter
+pub fn is_virtualenv(path: &Path) -> bool {
+    path.join(PYVENV_CFG).is_file()
+        && interpreter_paths(path)
+            .iter()
+            // symlink_metadata so a broken interpreter symlink still counts
+            .any(|interpreter| interpreter.symlink_metadata().is_ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_venv() {
+        let cfg = PyvenvCfg::parse(
+            "home = /usr/bin\ninclude-system-site-packages = false\nversion = 3.12.1\n\
+             executable = /usr/bin/python3.12\ncommand = /usr/bin/python3 -m venv /tmp/.venv\n",
+        );
+        assert_eq!(cfg.version.as_deref(), Some("3.12.1"));
+        assert_eq!(cfg.creator.as_deref(), Some("venv"));
+    }
+
+    #[test]
+    fn parses_virtualenv_version_info() {
+        let cfg = PyvenvCfg::parse(
+            "home = /usr/bin\nimplementation = CPython\nvirtualenv = 20.24.0\n\
+             include-system-site-packages = false\nbase-prefix = /usr\n\
+             version_info = 3.12.1.final.0\n",
+        );
+        assert_eq!(cfg.version.as_deref(), Some("3.12.1"));
+        assert_eq!(cfg.creator.as_deref(), Some("virtualenv 20.24.0"));
+    }
+
+    #[test]
+    fn parses_uv() {
+        let cfg = PyvenvCfg::parse(
+            "home = /usr/bin\nimplementation = CPython\nuv = 0.4.0\nversion_info = 3.11.4\n\
+             include-system-site-packages = false\n",
+        );
+        assert_eq!(cfg.version.as_deref(), Some("3.11.4"));
+        assert_eq!(cfg.creator.as_deref(), Some("uv 0.4.0"));
+    }
+
+    #[test]
+    fn ignores_what_it_doesnt_understand() {
+        assert_eq!(PyvenvCfg::parse(""), PyvenvCfg::default());
+        assert_eq!(
+            PyvenvCfg::parse("not a setting\nversion_info = final\n"),
+            PyvenvCfg::default()
+        );
+    }
+}
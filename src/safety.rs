@@ -0,0 +1,220 @@
+use std::path::{Path, PathBuf};
+
+use crate::pyvenv::PYVENV_CFG;
+use crate::{trash, Environment, Errors};
+
+/// Directories that are never an environment, whatever a tool tells us
+const PROTECTED_PATHS: &[&str] = &[
+    "/",
+    "/Applications",
+    "/Library",
+    "/System",
+    "/Users",
+    "/bin",
+    "/boot",
+    "/dev",
+    "/etc",
+    "/home",
+    "/lib",
+    "/lib64",
+    "/opt",
+    "/opt/homebrew",
+    "/proc",
+    "/root",
+    "/sbin",
+    "/srv",
+    "/sys",
+    "/tmp",
+    "/usr",
+    "/usr/local",
+    "/var",
+    "C:\\",
+    "C:\\Program Files",
+    "C:\\Program Files (x86)",
+    "C:\\Windows",
+];
+
+fn refuse(path: &Path, reason: &str) -> Errors {
+    Errors::InvalidVenv {
+        path: path.to_path_buf(),
+        reason: reason.to_string(),
+    }
+}
+
+/// PEP 582 `__pypackages__` directories hold one `X.Y/lib` per Python version instead of a
+/// `pyvenv.cfg`
+fn is_pypackages(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|name| name == "__pypackages__")
+        && std::fs::read_dir(path).is_ok_and(|mut entries| {
+            entries.all(|entry| {
+                entry.is_ok_and(|entry| {
+                    let name = entry.file_name().to_string_lossy().to_string();
+                    name.split('.').all(|part| part.parse::<u32>().is_ok())
+                        && entry.path().join("lib").is_dir()
+                })
+            })
+        })
+}
+
+/// where the Python interpreters on the PATH are installed, eg `/usr` for `/usr/bin/python3`
+fn system_prefixes() -> Vec<PathBuf> {
+    ["python3", "python"]
+        .iter()
+        .filter_map(|name| which::which_all(name).ok())
+        .flatten()
+        .filter_map(|interpreter| interpreter.canonicalize().ok())
+        .filter_map(|interpreter| Some(interpreter.parent()?.parent()?.to_path_buf()))
+        .collect()
+}
+
+/// checks that an environment is safe to remove, before anything touches it. It has to look like
+/// an environment, and can't be somewhere important or contain the directory we were scanning.
+pub fn validate(environment: &Environment) -> Result<(), Errors> {
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    validate_with_home(environment, home.as_deref())
+}
+
+/// [validate], with the home directory passed in
+fn validate_with_home(environment: &Environment, home: Option<&Path>) -> Result<(), Errors> {
+    let path = &environment.path;
+    if path.as_os_str().is_empty() {
+        return Err(refuse(path, "the path is empty"));
+    }
+    let canonical = path
+        .canonicalize()
+        .map_err(|err| refuse(path, &format!("couldn't resolve it: {:?}", err)))?;
+    // these come first, so nothing that happens to look like an environment gets past them
+    if PROTECTED_PATHS
+        .iter()
+        .any(|protected| canonical == Path::new(protected))
+    {
+        return Err(refuse(path, "it's a system directory"));
+    }
+    if let Some(home) = home.and_then(|home| home.canonicalize().ok()) {
+        if home.starts_with(&canonical) {
+            return Err(refuse(path, "it's your home directory, or contains it"));
+        }
+    }
+    if !canonical.is_dir() {
+        return Err(refuse(path, "it's not a directory"));
+    }
+    if trash::in_trash(&canonical) {
+        return Err(refuse(path, "it's already in the trash"));
+    }
+    if !(canonical.join(PYVENV_CFG).is_file()
+        || canonical.join("conda-meta").is_dir()
+        || is_pypackages(&canonical))
+    {
+        return Err(refuse(
+            path,
+            "it doesn't have a pyvenv.cfg or conda-meta, so doesn't look like an environment",
+        ));
+    }
+    if system_prefixes().contains(&canonical) {
+        return Err(refuse(path, "it's where a system Python is installed"));
+    }
+    if let Some(root) = environment
+        .root
+        .as_ref()
+        .and_then(|root| root.canonicalize().ok())
+    {
+        if root != canonical && root.starts_with(&canonical) {
+            return Err(refuse(path, "it contains the directory being scanned"));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the reason [validate_with_home] refuses an environment, or `None` if it's allowed
+    fn refusal(path: &Path, root: Option<&Path>, home: Option<&Path>) -> Option<String> {
+        let mut environment = Environment::new(path.to_path_buf(), "pyvenv");
+        environment.root = root.map(Path::to_path_buf);
+        match validate_with_home(&environment, home) {
+            Ok(()) => None,
+            Err(Errors::InvalidVenv { reason, .. }) => Some(reason),
+            Err(err) => panic!("Unexpected error {}", err),
+        }
+    }
+
+    /// makes a directory with a `pyvenv.cfg` in it
+    fn make_environment(path: &Path) {
+        std::fs::create_dir_all(path).unwrap();
+        std::fs::write(path.join(PYVENV_CFG), "home = /usr/bin\n").unwrap();
+    }
+
+    #[test]
+    fn refuses_an_empty_path() {
+        let reason = refusal(Path::new(""), None, None).unwrap();
+        assert!(reason.contains("empty"), "{}", reason);
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn refuses_protected_paths() {
+        for path in ["/", "/usr", "/tmp"] {
+            let reason = refusal(Path::new(path), None, None).unwrap();
+            assert!(reason.contains("system directory"), "{}: {}", path, reason);
+        }
+    }
+
+    #[test]
+    fn refuses_home_and_its_ancestors() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home").join("me");
+        make_environment(&home);
+        for path in [home.as_path(), home.parent().unwrap()] {
+            let reason = refusal(path, None, Some(&home)).unwrap();
+            assert!(reason.contains("home directory"), "{}", reason);
+        }
+    }
+
+    #[test]
+    fn refuses_directories_that_dont_look_like_environments() {
+        let dir = tempfile::tempdir().unwrap();
+        let reason = refusal(dir.path(), None, None).unwrap();
+        assert!(reason.contains("doesn't look like"), "{}", reason);
+        let missing = dir.path().join("missing");
+        let reason = refusal(&missing, None, None).unwrap();
+        assert!(reason.contains("couldn't resolve"), "{}", reason);
+    }
+
+    #[test]
+    fn refuses_an_ancestor_of_the_scan_root() {
+        let dir = tempfile::tempdir().unwrap();
+        let environment = dir.path().join(".venv");
+        make_environment(&environment);
+        let root = environment.join("lib");
+        std::fs::create_dir_all(&root).unwrap();
+        let reason = refusal(&environment, Some(&root), None).unwrap();
+        assert!(reason.contains("directory being scanned"), "{}", reason);
+    }
+
+    #[test]
+    fn refuses_environments_already_in_a_trash() {
+        let dir = tempfile::tempdir().unwrap();
+        let environment = dir.path().join(".Trash-1000").join("files").join(".venv");
+        make_environment(&environment);
+        let reason = refusal(&environment, None, None).unwrap();
+        assert!(reason.contains("already in the trash"), "{}", reason);
+    }
+
+    #[test]
+    fn allows_environments() {
+        let dir = tempfile::tempdir().unwrap();
+        let home = dir.path().join("home");
+        std::fs::create_dir_all(&home).unwrap();
+        let environment = dir.path().join("project").join(".venv");
+        make_environment(&environment);
+        assert_eq!(refusal(&environment, Some(dir.path()), Some(&home)), None);
+        // the root is the environment itself when it's scanned directly
+        assert_eq!(refusal(&environment, Some(&environment), Some(&home)), None);
+        let conda = dir.path().join("conda");
+        std::fs::create_dir_all(conda.join("conda-meta")).unwrap();
+        assert_eq!(refusal(&conda, None, Some(&home)), None);
+    }
+}
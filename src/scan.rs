@@ -0,0 +1,778 @@
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Mutex};
+use std::time::{Duration, SystemTime};
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+use crate::age;
+use crate::ignores::IgnoreFiles;
+use crate::inuse::Processes;
+use crate::keep::{self, Excludes};
+
+use crate::detectors::{builtin_detectors, Detector, Environment};
+use crate::markers::{self, DEFAULT_MARKERS};
+use crate::mounts::pseudo_mount_points;
+use crate::size::{DiskUsage, SizeMode};
+use crate::trash;
+use crate::Errors;
+
+/// The order to return environments in, when the whole scan is collected before returning any
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SortOrder {
+    /// Alphabetically by path
+    Path,
+    /// Largest first
+    Size,
+}
+
+impl SortOrder {
+    pub fn sort(&self, environments: &mut [Environment]) {
+        match self {
+            SortOrder::Path => environments.sort_by(|a, b| a.path.cmp(&b.path)),
+            SortOrder::Size => {
+                environments.sort_by(|a, b| b.size.cmp(&a.size).then_with(|| a.path.cmp(&b.path)))
+            }
+        }
+    }
+}
+
+/// Builds up the options for a scan, then walks the tree with [Scanner::scan]
+pub struct Scanner {
+    roots: Vec<PathBuf>,
+    max_depth: Option<usize>,
+    deep: bool,
+    debug: bool,
+    markers: Vec<String>,
+    detectors: Vec<Box<dyn Detector>>,
+    jobs: usize,
+    sort: Option<SortOrder>,
+    size_mode: SizeMode,
+    older_than: Option<Duration>,
+    min_size: Option<u64>,
+    top: Option<usize>,
+    check_in_use: bool,
+    excludes: Excludes,
+    respect_ignores: bool,
+    one_file_system: bool,
+    pseudo_filesystems: bool,
+    orphans: bool,
+    keep_active: bool,
+}
+
+impl Default for Scanner {
+    fn default() -> Self {
+        Self {
+            roots: vec![],
+            max_depth: None,
+            deep: false,
+            debug: false,
+            markers: DEFAULT_MARKERS.iter().map(|m| m.to_string()).collect(),
+            detectors: builtin_detectors(),
+            jobs: 1,
+            sort: None,
+            size_mode: SizeMode::default(),
+            older_than: None,
+            min_size: None,
+            top: None,
+            check_in_use: false,
+            excludes: Excludes::default(),
+            respect_ignores: false,
+            one_file_system: false,
+            pseudo_filesystems: false,
+            orphans: false,
+            keep_active: false,
+        }
+    }
+}
+
+impl Scanner {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a path to search, the current directory is used if none are given
+    pub fn root(mut self, path: impl Into<PathBuf>) -> Self {
+        self.roots.push(path.into());
+        self
+    }
+
+    /// Maximum depth to recurse into each root
+    pub fn max_depth(mut self, max_depth: Option<usize>) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    /// Keep walking into projects after finding a marker file
+    pub fn deep(mut self, deep: bool) -> Self {
+        self.deep = deep;
+        self
+    }
+
+    /// Print what's going on to stderr
+    pub fn debug(mut self, debug: bool) -> Self {
+        self.debug = debug;
+        self
+    }
+
+    /// Replace the project marker patterns, see [crate::markers]
+    pub fn markers(mut self, markers: Vec<String>) -> Self {
+        self.markers = markers;
+        self
+    }
+
+    /// Replace the detectors, they're tried in order and the first to find a path names its kind
+    pub fn detectors(mut self, detectors: Vec<Box<dyn Detector>>) -> Self {
+        self.detectors = detectors;
+        self
+    }
+
+    /// Work out sizes on this many threads while the tree is walked. More than one job means the
+    /// whole scan finishes before the first environment is returned, sorted by path unless
+    /// [Scanner::sort] says otherwise, so the results are the same from run to run.
+    pub fn jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Finish the whole scan before returning anything, then return environments in this order
+    pub fn sort(mut self, sort: Option<SortOrder>) -> Self {
+        self.sort = sort;
+        self
+    }
+
+    /// How to measure environments
+    pub fn size_mode(mut self, size_mode: SizeMode) -> Self {
+        self.size_mode = size_mode;
+        self
+    }
+
+    /// Only return environments that haven't been used, and whose projects haven't been touched,
+    /// for at least this long
+    pub fn older_than(mut self, older_than: Option<Duration>) -> Self {
+        self.older_than = older_than;
+        self
+    }
+
+    /// Only return environments at least this many bytes, in the scanner's [SizeMode]
+    pub fn min_size(mut self, min_size: Option<u64>) -> Self {
+        self.min_size = min_size;
+        self
+    }
+
+    /// Only return the largest `top` environments. This finishes the whole scan before returning
+    /// anything, and sorts by size unless [Scanner::sort] says otherwise.
+    pub fn top(mut self, top: Option<usize>) -> Self {
+        self.top = top;
+        self
+    }
+
+    /// Look at running processes and fill in [Environment::in_use]. The processes are looked at
+    /// once when the scan starts, so check again before removing anything.
+    pub fn check_in_use(mut self, check_in_use: bool) -> Self {
+        self.check_in_use = check_in_use;
+        self
+    }
+
+    /// Don't walk into paths matching these, or return environments inside them
+    pub fn excludes(mut self, excludes: Excludes) -> Self {
+        self.excludes = excludes;
+        self
+    }
+
+    /// Skip whatever `.gitignore`, `.ignore` and `.sweepignore` files ignore, except environments
+    pub fn respect_ignores(mut self, respect_ignores: bool) -> Self {
+        self.respect_ignores = respect_ignores;
+        self
+    }
+
+    /// Don't walk into other filesystems mounted inside a root
+    pub fn one_file_system(mut self, one_file_system: bool) -> Self {
+        self.one_file_system = one_file_system;
+        self
+    }
+
+    /// Walk into pseudo filesystems like `/proc` and overlay mounts too, see
+    /// [crate::mounts::PSEUDO_FILESYSTEMS]
+    pub fn pseudo_filesystems(mut self, pseudo_filesystems: bool) -> Self {
+        self.pseudo_filesystems = pseudo_filesystems;
+        self
+    }
+
+    /// Instead of walking the roots, look through tools' own environment directories for
+    /// environments whose project has been deleted, see [Detector::detect_orphans]
+    pub fn orphans(mut self, orphans: bool) -> Self {
+        self.orphans = orphans;
+        self
+    }
+
+    /// Leave out environments their tool is using, so only a project's other environments are
+    /// returned, see [Environment::active]. When a tool that can have several environments for a
+    /// project doesn't say which it's using, any of them might be, so they're all left out.
+    pub fn keep_active(mut self, keep_active: bool) -> Self {
+        self.keep_active = keep_active;
+        self
+    }
+
+    /// whether [Scanner::keep_active] leaves an environment out
+    fn keeps_active(&self, environment: &Environment) -> bool {
+        if !self.keep_active {
+            return false;
+        }
+        match environment.active {
+            Some(active) => active,
+            // nothing uses an environment whose project has gone
+            None if environment.orphaned => false,
+            None => self
+                .detectors
+                .iter()
+                .any(|detector| detector.name() == environment.kind && detector.tracks_active()),
+        }
+    }
+
+    /// Start walking, environments are found lazily as the iterator is consumed
+    pub fn scan(self) -> Scan {
+        let roots = match (self.orphans, self.roots.is_empty()) {
+            (true, _) => vec![],
+            (false, true) => vec![PathBuf::from(".")],
+            (false, false) => self.roots.clone(),
+        };
+        Scan {
+            roots: roots.into(),
+            walker: None,
+            checked_paths: HashSet::new(),
+            found_venvs: HashSet::new(),
+            pending: VecDeque::new(),
+            failures: VecDeque::new(),
+            current_root: None,
+            canonical_root: None,
+            pseudo_mounts: match self.pseudo_filesystems {
+                true => HashSet::new(),
+                false => pseudo_mount_points(),
+            },
+            processes: None,
+            orphans_pending: self.orphans,
+            ignores: IgnoreFiles::new(self.debug),
+            prune: false,
+            collected: None,
+            options: self,
+        }
+    }
+}
+
+/// Directories that never hold a project's environment, so aren't worth walking
+pub const SKIPPED_DIRS: &[&str] = &[
+    ".git",
+    ".hg",
+    ".svn",
+    "node_modules",
+    "__pycache__",
+    ".mypy_cache",
+    ".pytest_cache",
+    ".ruff_cache",
+];
+
+type Walker = walkdir::FilterEntry<walkdir::IntoIter, fn(&walkdir::DirEntry) -> bool>;
+
+fn not_skipped_dir(entry: &walkdir::DirEntry) -> bool {
+    // never skip a root, even if someone asks us to scan inside node_modules
+    entry.depth() == 0
+        || !entry.file_type().is_dir()
+        || !(SKIPPED_DIRS.iter().any(|name| entry.file_name() == *name)
+            // what's in the trash has already been dealt with
+            || trash::is_trash(entry.path()))
+}
+
+/// An in-progress scan, yielding environments as they're found
+pub struct Scan {
+    options: Scanner,
+    roots: VecDeque<PathBuf>,
+    walker: Option<Walker>,
+    /// Project directories we've run the detectors on
+    checked_paths: HashSet<PathBuf>,
+    /// Environments we've already reported, so we don't report them twice or walk into them
+    found_venvs: HashSet<PathBuf>,
+    pending: VecDeque<Environment>,
+    /// Problems the detectors had, returned before anything else so they're reported
+    failures: VecDeque<Errors>,
+    /// The root the walker is currently in
+    current_root: Option<PathBuf>,
+    /// The current root with symlinks resolved, to compare walked paths with mount points
+    canonical_root: Option<PathBuf>,
+    /// Where pseudo filesystems are mounted, unless we're walking them
+    pseudo_mounts: HashSet<PathBuf>,
+    /// What running processes were using when the scan started, if we're checking
+    processes: Option<Processes>,
+    /// Set until we've asked the detectors for orphaned environments, if we're going to
+    orphans_pending: bool,
+    /// The ignore files read so far, if we're respecting them
+    ignores: IgnoreFiles,
+    /// Set by [Scan::check_path] when the walker shouldn't go any further into the current directory
+    prune: bool,
+    /// The sized and sorted results, when we're collecting the whole scan up front
+    collected: Option<VecDeque<Result<Environment, Errors>>>,
+}
+
+impl Scan {
+    fn next_walker(&mut self) -> Option<Walker> {
+        let root = self.roots.pop_front()?;
+        if self.options.debug {
+            eprintln!("Walking path: {:?}", root);
+        }
+        self.current_root = Some(root.clone());
+        self.canonical_root = root.canonicalize().ok();
+        self.ignores.clear();
+        // files first, so we see a project's marker files before walking into its directories
+        let mut walker = WalkDir::new(root).sort_by(|a, b| {
+            a.file_type()
+                .is_dir()
+                .cmp(&b.file_type().is_dir())
+                .then_with(|| a.file_name().cmp(b.file_name()))
+        });
+        if let Some(max_depth) = self.options.max_depth {
+            walker = walker.max_depth(max_depth);
+        }
+        if self.options.one_file_system {
+            walker = walker.same_file_system(true);
+        }
+        Some(
+            walker
+                .into_iter()
+                .filter_entry(not_skipped_dir as fn(&_) -> bool),
+        )
+    }
+
+    /// whether a walked directory is where a pseudo filesystem is mounted. Roots are always walked.
+    fn is_pseudo_mount(&self, entry: &walkdir::DirEntry) -> bool {
+        if self.pseudo_mounts.is_empty() || entry.depth() == 0 || !entry.file_type().is_dir() {
+            return false;
+        }
+        let (Some(root), Some(canonical_root)) = (&self.current_root, &self.canonical_root) else {
+            return false;
+        };
+        // the walk doesn't follow symlinks, so below the root the path is already canonical
+        entry
+            .path()
+            .strip_prefix(root)
+            .is_ok_and(|relative| self.pseudo_mounts.contains(&canonical_root.join(relative)))
+    }
+
+    /// looks for virtualenvs, either at the walked entry or belonging to a project it marks. Most
+    /// entries have nothing to do with either, and give an empty list.
+    fn check_path(&mut self, entry: walkdir::DirEntry) -> Result<Vec<Environment>, Errors> {
+        // a detector already told us about this one, probably from its project
+        if entry.file_type().is_dir() && self.found_venvs.contains(entry.path()) {
+            self.prune = true;
+            if self.options.debug {
+                eprintln!("Already found virtualenv {}", entry.path().display());
+            }
+            return Ok(vec![]);
+        }
+        let results = self.detect_entry(&entry)?;
+        if !results.is_empty() {
+            if self.options.debug {
+                eprintln!("Environment found at {:?}", entry.path());
+            }
+            self.prune = true;
+            return Ok(self.dedupe_found(results));
+        }
+        let marker = entry.file_name().to_string_lossy().to_string();
+        if !entry.file_type().is_file()
+            || markers::matching_marker(&self.options.markers, &marker).is_none()
+        {
+            return Ok(vec![]);
+        }
+        let project_path = entry
+            .path()
+            .parent()
+            .expect("Can't find the parent path for a file we just found?");
+        // the walker yields a directory's files first, so skipping now skips the rest of the project
+        self.prune = !self.options.deep;
+        if !self.checked_paths.insert(project_path.to_path_buf()) {
+            if self.options.debug {
+                eprintln!("Already checked project {}", project_path.display());
+            }
+            return Ok(vec![]);
+        }
+        if self.options.debug {
+            eprintln!("Project path: {:?} (found {})", project_path, marker);
+        }
+        let mut results = self.detect_project(project_path);
+        if !self.options.deep {
+            // we won't walk the project, so look for environments with other names in it
+            for child in WalkDir::new(project_path)
+                .min_depth(1)
+                .max_depth(1)
+                .into_iter()
+                .filter_entry(not_skipped_dir)
+                .filter_map(|child| child.ok())
+            {
+                results.extend(self.detect_entry(&child).unwrap_or_default());
+            }
+        }
+        let results = self
+            .dedupe_found(results)
+            .into_iter()
+            .map(|mut found| {
+                found
+                    .project
+                    .get_or_insert_with(|| project_path.to_path_buf());
+                found.marker = Some(marker.clone());
+                found
+            })
+            .collect::<Vec<_>>();
+        if results.is_empty() && self.options.debug {
+            eprintln!("No environments found for {}", project_path.display());
+        }
+        Ok(results)
+    }
+
+    /// runs all the entry detectors against a walked path
+    fn detect_entry(&self, entry: &walkdir::DirEntry) -> Result<Vec<Environment>, Errors> {
+        let mut results = vec![];
+        for detector in self.options.detectors.iter() {
+            results.extend(detector.detect_entry(entry)?);
+        }
+        Ok(results)
+    }
+
+    /// runs every detector, a failing detector doesn't stop the others but its error is kept to
+    /// report, along with anything it found before it failed
+    fn run_detectors(
+        &mut self,
+        detect: impl Fn(&dyn Detector) -> Result<Vec<Environment>, Errors>,
+    ) -> Vec<Environment> {
+        let mut results = vec![];
+        for detector in self.options.detectors.iter() {
+            let err = match detect(detector.as_ref()) {
+                Ok(found) => {
+                    results.extend(found);
+                    continue;
+                }
+                Err(Errors::Incomplete { found, source }) => {
+                    results.extend(found);
+                    *source
+                }
+                Err(err) => err,
+            };
+            if self.options.debug {
+                eprintln!("{} detector failed: {}", detector.name(), err);
+            }
+            self.failures.push_back(err);
+        }
+        results
+    }
+
+    /// runs all the project detectors, see [Scan::run_detectors]
+    fn detect_project(&mut self, project_path: &Path) -> Vec<Environment> {
+        self.run_detectors(|detector| detector.detect_project(project_path))
+    }
+
+    /// asks every detector for orphaned environments, see [Scan::run_detectors]
+    fn detect_orphans(&mut self) -> Vec<Environment> {
+        let results = self.run_detectors(|detector| detector.detect_orphans());
+        self.dedupe_found(results)
+    }
+
+    /// drops anything we've already reported, and remembers the rest
+    fn dedupe_found(&mut self, results: Vec<Environment>) -> Vec<Environment> {
+        results
+            .into_iter()
+            .filter(|found| self.found_venvs.insert(found.path.clone()))
+            .collect()
+    }
+
+    /// checks the environment against [Scanner::older_than]. If we couldn't tell when it was last
+    /// used, it might not be old enough, so it's left out.
+    fn is_too_recent(&self, environment: &Environment) -> bool {
+        let Some(older_than) = self.options.older_than else {
+            return false;
+        };
+        let Some(last_used) = environment.last_used else {
+            return true;
+        };
+        match SystemTime::now().checked_sub(older_than) {
+            Some(cutoff) => last_used > cutoff,
+            None => true,
+        }
+    }
+
+    /// checks the environment against [Scanner::min_size], once it's been measured
+    fn is_big_enough(&self, environment: &Environment) -> bool {
+        let big_enough = environment.size >= self.options.min_size.unwrap_or(0);
+        if !big_enough && self.options.debug {
+            eprintln!("Too small: {:?}", environment.path);
+        }
+        big_enough
+    }
+
+    /// walks until the next environment is found, without working out its size
+    fn next_found(&mut self) -> Option<Result<Environment, Errors>> {
+        loop {
+            if let Some(err) = self.failures.pop_front() {
+                return Some(Err(err));
+            }
+            if let Some(mut environment) = self.pending.pop_front() {
+                if self.options.excludes.covers(&environment.path) {
+                    if self.options.debug {
+                        eprintln!("Excluded: {:?}", environment.path);
+                    }
+                    continue;
+                }
+                if self.options.keeps_active(&environment) {
+                    if self.options.debug {
+                        eprintln!("Active, or might be: {:?}", environment.path);
+                    }
+                    continue;
+                }
+                environment.last_used = age::last_used(&environment);
+                if self.is_too_recent(&environment) {
+                    if self.options.debug {
+                        eprintln!("Used too recently: {:?}", environment.path);
+                    }
+                    continue;
+                }
+                if self.options.check_in_use {
+                    environment.in_use = self
+                        .processes
+                        .get_or_insert_with(Processes::snapshot)
+                        .using(&environment.path);
+                }
+                environment.kept_by = keep::kept_by(&environment);
+                return Some(Ok(environment));
+            }
+            let entry = match self.walker.as_mut().and_then(|walker| walker.next()) {
+                Some(entry) => entry,
+                None => {
+                    match self.next_walker() {
+                        Some(walker) => self.walker = Some(walker),
+                        None if self.orphans_pending => {
+                            self.orphans_pending = false;
+                            let orphans = self.detect_orphans();
+                            self.pending.extend(orphans);
+                        }
+                        None => return None,
+                    }
+                    continue;
+                }
+            };
+            let entry = match entry {
+                Ok(val) => val,
+                Err(err) => {
+                    if self.options.debug {
+                        eprintln!(
+                            "Error getting direntry, did you just delete the parent? {:?}",
+                            err
+                        );
+                    }
+                    continue;
+                }
+            };
+            if !entry.path().exists() {
+                if self.options.debug {
+                    eprintln!("Path doesn't exist: {:?}", entry.path());
+                }
+                continue;
+            }
+            if self.is_pseudo_mount(&entry) {
+                if self.options.debug {
+                    eprintln!("Pseudo filesystem: {:?}", entry.path());
+                }
+                if let Some(walker) = self.walker.as_mut() {
+                    walker.skip_current_dir();
+                }
+                continue;
+            }
+            if self.options.excludes.matches(entry.path()) {
+                if self.options.debug {
+                    eprintln!("Excluded: {:?}", entry.path());
+                }
+                if entry.file_type().is_dir() {
+                    if let Some(walker) = self.walker.as_mut() {
+                        walker.skip_current_dir();
+                    }
+                }
+                continue;
+            }
+            // environments are nearly always ignored, so check for one before skipping anything
+            if self.options.respect_ignores
+                && self
+                    .ignores
+                    .is_ignored(entry.path(), entry.file_type().is_dir(), entry.depth())
+                && !self
+                    .detect_entry(&entry)
+                    .is_ok_and(|found| !found.is_empty())
+            {
+                if self.options.debug {
+                    eprintln!("Ignored: {:?}", entry.path());
+                }
+                if entry.file_type().is_dir() {
+                    if let Some(walker) = self.walker.as_mut() {
+                        walker.skip_current_dir();
+                    }
+                }
+                continue;
+            }
+            self.prune = false;
+            let result = self.check_path(entry);
+            if self.prune {
+                if let Some(walker) = self.walker.as_mut() {
+                    walker.skip_current_dir();
+                }
+            }
+            match result {
+                Ok(found) => self
+                    .pending
+                    .extend(found.into_iter().map(|mut environment| {
+                        environment.root = self.current_root.clone();
+                        environment
+                    })),
+                Err(err) => return Some(Err(err)),
+            }
+        }
+    }
+
+    /// walks the whole tree, working out sizes on worker threads as environments turn up
+    fn collect_all(&mut self) -> VecDeque<Result<Environment, Errors>> {
+        let (sender, receiver) = mpsc::channel::<Environment>();
+        let receiver = Mutex::new(receiver);
+        let mut errors = vec![];
+        let size_mode = self.options.size_mode;
+        let mut environments = std::thread::scope(|scope| {
+            let workers = (0..self.options.jobs)
+                .map(|_| {
+                    scope.spawn(|| {
+                        let mut sized = vec![];
+                        // the lock is only held while waiting, not while walking the environment
+                        while let Ok(mut environment) = receiver
+                            .lock()
+                            .expect("Size worker queue lock was poisoned")
+                            .recv()
+                        {
+                            measure(&mut environment, size_mode);
+                            sized.push(environment);
+                        }
+                        sized
+                    })
+                })
+                .collect::<Vec<_>>();
+            while let Some(found) = self.next_found() {
+                match found {
+                    Ok(environment) => sender
+                        .send(environment)
+                        .expect("Size workers stopped before the walk finished"),
+                    Err(err) => errors.push(err),
+                }
+            }
+            drop(sender);
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().expect("Size worker panicked"))
+                .filter(|environment| self.is_big_enough(environment))
+                .collect::<Vec<_>>()
+        });
+        let default_sort = match self.options.top {
+            Some(top) => {
+                SortOrder::Size.sort(&mut environments);
+                environments.truncate(top);
+                SortOrder::Size
+            }
+            None => SortOrder::Path,
+        };
+        self.options
+            .sort
+            .unwrap_or(default_sort)
+            .sort(&mut environments);
+        errors
+            .into_iter()
+            .map(Err)
+            .chain(environments.into_iter().map(Ok))
+            .collect()
+    }
+}
+
+/// fills in the sizes of an environment
+fn measure(environment: &mut Environment, size_mode: SizeMode) {
+    environment.usage = DiskUsage::measure(&environment.path, size_mode);
+    environment.size = environment.usage.size(size_mode);
+}
+
+impl Iterator for Scan {
+    type Item = Result<Environment, Errors>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.options.jobs == 1 && self.options.sort.is_none() && self.options.top.is_none() {
+            loop {
+                let mut environment = match self.next_found()? {
+                    Ok(environment) => environment,
+                    Err(err) => return Some(Err(err)),
+                };
+                measure(&mut environment, self.options.size_mode);
+                if self.is_big_enough(&environment) {
+                    return Some(Ok(environment));
+                }
+            }
+        }
+        if self.collected.is_none() {
+            self.collected = Some(self.collect_all());
+        }
+        self.collected.as_mut()?.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn poetry_env(name: &str, active: Option<bool>) -> Environment {
+        let mut environment = Environment::new(PathBuf::from("/nonexistent").join(name), "poetry");
+        environment.active = active;
+        environment
+    }
+
+    #[test]
+    fn keep_active_leaves_out_the_active_environment() {
+        let scanner = Scanner::new().keep_active(true);
+        assert!(scanner.keeps_active(&poetry_env("app-py3.12", Some(true))));
+        assert!(!scanner.keeps_active(&poetry_env("app-py3.11", Some(false))));
+    }
+
+    #[test]
+    fn keep_active_leaves_out_environments_that_might_be_active() {
+        let scanner = Scanner::new().keep_active(true);
+        assert!(scanner.keeps_active(&poetry_env("app-py3.12", None)));
+        assert!(scanner.keeps_active(&poetry_env("app-py3.11", None)));
+    }
+
+    #[test]
+    fn keep_active_sweeps_orphans_and_single_environment_tools() {
+        let scanner = Scanner::new().keep_active(true);
+        let mut orphan = poetry_env("gone-py3.12", None);
+        orphan.orphaned = true;
+        assert!(!scanner.keeps_active(&orphan));
+        let in_project = Environment::new(PathBuf::from("/nonexistent/.venv"), "in-project");
+        assert!(!scanner.keeps_active(&in_project));
+    }
+
+    #[test]
+    fn without_keep_active_nothing_is_left_out() {
+        let scanner = Scanner::new();
+        assert!(!scanner.keeps_active(&poetry_env("app-py3.12", Some(true))));
+        assert!(!scanner.keeps_active(&poetry_env("app-py3.11", None)));
+    }
+
+    #[test]
+    fn older_than_leaves_out_environments_of_unknown_age() {
+        let scan = Scanner::new()
+            .older_than(Some(Duration::from_secs(3600)))
+            .scan();
+        let mut environment = Environment::new(PathBuf::from("/nonexistent/.venv"), "in-project");
+        assert!(scan.is_too_recent(&environment));
+        environment.last_used = Some(SystemTime::now());
+        assert!(scan.is_too_recent(&environment));
+        environment.last_used = SystemTime::now().checked_sub(Duration::from_secs(7200));
+        assert!(!scan.is_too_recent(&environment));
+    }
+}
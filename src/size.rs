@@ -0,0 +1,183 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use walkdir::WalkDir;
+
+/// How to work out the size of an environment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SizeMode {
+    /// Add up the length of every file, like `du --apparent-size`
+    #[default]
+    Apparent,
+    /// Add up the blocks allocated on disk, counting hardlinked files once, like `du`
+    Allocated,
+}
+
+/// The different ways of measuring an environment
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiskUsage {
+    /// The total length of the files
+    pub apparent: u64,
+    /// Blocks allocated on disk, counting each inode once. Same as `apparent` in apparent mode.
+    pub allocated: u64,
+    /// Blocks that would actually be released by deleting it, which leaves out files that are
+    /// hardlinked from somewhere else. Same as `apparent` in apparent mode.
+    pub freed: u64,
+}
+
+impl DiskUsage {
+    /// measures a directory
+    pub fn measure(path: &Path, mode: SizeMode) -> Self {
+        match mode {
+            SizeMode::Apparent => {
+                let apparent = get_size_on_disk(path);
+                Self {
+                    apparent,
+                    allocated: apparent,
+                    freed: apparent,
+                }
+            }
+            SizeMode::Allocated => get_allocated_usage(path),
+        }
+    }
+
+    /// the number to show as "the size", depending on the mode
+    pub fn size(&self, mode: SizeMode) -> u64 {
+        match mode {
+            SizeMode::Apparent => self.apparent,
+            SizeMode::Allocated => self.allocated,
+        }
+    }
+}
+
+/// gets the size on disk of a directory
+pub fn get_size_on_disk(path: &Path) -> u64 {
+    let mut size = 0;
+    for entry in WalkDir::new(path) {
+        let entry = match entry {
+            Ok(val) => val,
+            Err(_err) => {
+                // eprintln!("Error getting direntry, did you just delete the parent? {:?}", err);
+                continue;
+            }
+        };
+        // not following symlinks, which would update the interpreter symlink's access time that
+        // age.rs looks at
+        if entry.file_type().is_file() {
+            // it can disappear between listing and reading, or we might not be allowed to read it
+            if let Ok(metadata) = entry.metadata() {
+                size += metadata.len();
+            }
+        }
+    }
+    size
+}
+
+/// walks a directory counting allocated blocks once per inode, and how many of each inode's links
+/// are inside the directory so we know if deleting it would actually free the space
+#[cfg(unix)]
+fn get_allocated_usage(path: &Path) -> DiskUsage {
+    use std::collections::HashMap;
+    use std::os::unix::fs::MetadataExt;
+
+    struct Inode {
+        allocated: u64,
+        nlink: u64,
+        seen: u64,
+        is_dir: bool,
+    }
+
+    let mut apparent = 0;
+    let mut inodes: HashMap<(u64, u64), Inode> = HashMap::new();
+    // walkdir doesn't follow symlinks, so links out of the environment only count as themselves
+    for entry in WalkDir::new(path)
+        .into_iter()
+        .filter_map(|entry| entry.ok())
+    {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_file() {
+            apparent += metadata.len();
+        }
+        inodes
+            .entry((metadata.dev(), metadata.ino()))
+            .or_insert(Inode {
+                // st_blocks is always in 512 byte units, whatever the filesystem's block size is
+                allocated: metadata.blocks() * 512,
+                nlink: metadata.nlink(),
+                seen: 0,
+                is_dir: metadata.is_dir(),
+            })
+            .seen += 1;
+    }
+    let allocated = inodes.values().map(|inode| inode.allocated).sum();
+    // directories have extra links for "." and their children's "..", but those all live inside
+    let freed = inodes
+        .values()
+        .filter(|inode| inode.is_dir || inode.seen >= inode.nlink)
+        .map(|inode| inode.allocated)
+        .sum();
+    DiskUsage {
+        apparent,
+        allocated,
+        freed,
+    }
+}
+
+/// there's no portable way to get allocated blocks or link counts, so fall back to apparent size
+#[cfg(not(unix))]
+fn get_allocated_usage(path: &Path) -> DiskUsage {
+    DiskUsage::measure(path, SizeMode::Apparent)
+}
+
+/// parses a size like `200MB` or `1.5 GiB` into bytes
+pub fn parse_size(value: &str) -> Result<u64, String> {
+    byte_unit::Byte::parse_str(value, true)
+        .map(|size| size.as_u64())
+        .map_err(|err| err.to_string())
+}
+
+/// turns a number of bytes into a human readable string
+pub fn human_size(bytes: u64) -> String {
+    byte_unit::Byte::from_u64(bytes)
+        .get_appropriate_unit(byte_unit::UnitType::Decimal)
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    #[cfg(unix)]
+    fn allocated_usage_counts_hardlinks_once_and_only_frees_whats_inside() {
+        use std::os::unix::fs::MetadataExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let environment = dir.path().join(".venv");
+        let lib = environment.join("lib");
+        std::fs::create_dir_all(&lib).unwrap();
+        // both links to this are inside the environment
+        std::fs::write(environment.join("shared"), vec![1; 8192]).unwrap();
+        std::fs::hard_link(environment.join("shared"), lib.join("shared")).unwrap();
+        // and this one's linked from outside too, like a uv or pip cache
+        std::fs::write(dir.path().join("cached"), vec![2; 4096]).unwrap();
+        std::fs::hard_link(dir.path().join("cached"), lib.join("cached")).unwrap();
+
+        let blocks = |path: &Path| std::fs::symlink_metadata(path).unwrap().blocks() * 512;
+        let shared = blocks(&environment.join("shared"));
+        let cached = blocks(&lib.join("cached"));
+        let dirs = blocks(&environment) + blocks(&lib);
+        assert!(
+            cached > 0,
+            "the filesystem didn't allocate any blocks for the cached file"
+        );
+
+        let usage = get_allocated_usage(&environment);
+        assert_eq!(usage.apparent, 8192 * 2 + 4096);
+        assert_eq!(usage.allocated, dirs + shared + cached);
+        assert_eq!(usage.freed, dirs + shared);
+    }
+}
@@ -0,0 +1,226 @@
+//! Moving environments to the trash, following the freedesktop.org trash specification
+//! <https://specifications.freedesktop.org/trash-spec/latest/> so they can be restored with the
+//! usual desktop tools or `gio trash --restore`.
+
+use std::fs::OpenOptions;
+use std::io::{ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use crate::{env_var, safety, Environment, Errors};
+
+/// The home trash directory, `$XDG_DATA_HOME/Trash` or `~/.local/share/Trash`
+pub fn home_trash() -> Option<PathBuf> {
+    let data_home = match env_var("XDG_DATA_HOME") {
+        Some(data_home) => data_home,
+        None => PathBuf::from(std::env::var_os("HOME")?)
+            .join(".local")
+            .join("share"),
+    };
+    Some(data_home.join("Trash"))
+}
+
+/// whether a directory is a trash: the home trash, or a `.Trash` or `.Trash-$uid` directory at the
+/// top of a mounted filesystem
+pub fn is_trash(dir: &Path) -> bool {
+    let Some(name) = dir.file_name().and_then(|name| name.to_str()) else {
+        return false;
+    };
+    match name {
+        ".Trash" => true,
+        _ if name.starts_with(".Trash-") => true,
+        "Trash" => home_trash()
+            .and_then(|trash| trash.canonicalize().ok())
+            .is_some_and(|trash| dir.canonicalize().is_ok_and(|dir| dir == trash)),
+        _ => false,
+    }
+}
+
+/// whether a path is somewhere in a trash, see [is_trash]
+pub fn in_trash(path: &Path) -> bool {
+    path.ancestors().any(is_trash)
+}
+
+/// percent-encodes a path for the `Path=` key, leaving the characters URLs don't need escaped
+fn encode_path(path: &Path) -> String {
+    let mut encoded = String::new();
+    for byte in path.as_os_str().as_encoded_bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                encoded.push(*byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// claims a name in the trash by creating its `.trashinfo` file, which the spec says has to be
+/// done atomically before the file itself moves in
+fn reserve_name(trash: &Path, original: &Path) -> Result<(PathBuf, PathBuf), std::io::Error> {
+    let name = original
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_else(|| "environment".to_string());
+    let contents = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        encode_path(original),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+    for attempt in 1.. {
+        let candidate = match attempt {
+            1 => name.clone(),
+            _ => format!("{}.{}", name, attempt),
+        };
+        let info_path = trash.join("info").join(format!("{}.trashinfo", candidate));
+        let files_path = trash.join("files").join(&candidate);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(mut info) => {
+                if files_path.symlink_metadata().is_ok() {
+                    // a stray file without its info, leave it alone and try the next name
+                    drop(info);
+                    std::fs::remove_file(&info_path)?;
+                    continue;
+                }
+                info.write_all(contents.as_bytes())?;
+                return Ok((info_path, files_path));
+            }
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+    }
+    unreachable!("Ran out of names in the trash")
+}
+
+/// copies a directory tree, keeping symlinks as symlinks, for moves across filesystems
+fn copy_tree(from: &Path, to: &Path) -> Result<(), std::io::Error> {
+    // a read-only directory's permissions would stop us copying into it, so they go on last
+    let mut dir_permissions = vec![];
+    for entry in walkdir::WalkDir::new(from) {
+        let entry = entry?;
+        let target = to.join(
+            entry
+                .path()
+                .strip_prefix(from)
+                .expect("Walked outside the directory being copied"),
+        );
+        let file_type = entry.file_type();
+        if file_type.is_dir() {
+            std::fs::create_dir_all(&target)?;
+            dir_permissions.push((target, entry.metadata()?.permissions()));
+        } else if file_type.is_symlink() {
+            let link = std::fs::read_link(entry.path())?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(link, &target)?;
+            #[cfg(windows)]
+            std::os::windows::fs::symlink_file(link, &target)?;
+        } else {
+            std::fs::copy(entry.path(), &target)?;
+        }
+    }
+    // deepest first, so a parent never stops us changing its children
+    for (target, permissions) in dir_permissions.into_iter().rev() {
+        std::fs::set_permissions(&target, permissions)?;
+    }
+    Ok(())
+}
+
+/// moves a directory, copying it if the destination is on a different filesystem. If the copy
+/// worked but the original can't be removed, that's [Errors::PartiallyDeleted]: the copy is
+/// complete, so it stays in the trash where it can be restored.
+fn move_tree(from: &Path, to: &Path) -> Result<(), Errors> {
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == ErrorKind::CrossesDevices => {
+            if let Err(err) = copy_tree(from, to) {
+                // don't leave half a copy in the trash
+                let _ = std::fs::remove_dir_all(to);
+                return Err(Errors::io("move to the trash", from, err));
+            }
+            std::fs::remove_dir_all(from).map_err(|source| Errors::PartiallyDeleted {
+                path: from.to_path_buf(),
+                source,
+            })
+        }
+        Err(err) => Err(Errors::io("move to the trash", from, err)),
+    }
+}
+
+/// moves an environment into the home trash, returning the number of bytes moved.
+/// It's checked with [safety::validate] first.
+pub fn trash_environment(environment: &Environment) -> Result<u64, Errors> {
+    safety::validate(environment)?;
+    let trash = home_trash().ok_or_else(|| {
+        Errors::Other("Couldn't work out where the trash is, is $HOME set?".to_string())
+    })?;
+    let original = std::path::absolute(&environment.path)
+        .map_err(|err| Errors::io("get the absolute path of", &environment.path, err))?;
+    let (info_path, files_path) = std::fs::create_dir_all(trash.join("files"))
+        .and_then(|_| std::fs::create_dir_all(trash.join("info")))
+        .and_then(|_| reserve_name(&trash, &original))
+        .map_err(|err| Errors::io("move to the trash", &environment.path, err))?;
+    move_tree(&original, &files_path).inspect_err(|err| {
+        // the info file has to stay with a complete copy, or it can't be restored
+        if !matches!(err, Errors::PartiallyDeleted { .. }) {
+            let _ = std::fs::remove_file(&info_path);
+        }
+    })?;
+    Ok(environment.size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_paths_for_trashinfo() {
+        assert_eq!(
+            encode_path(Path::new("/tmp/my app/.venv")),
+            "/tmp/my%20app/.venv"
+        );
+        assert_eq!(encode_path(Path::new("/tmp/a%b")), "/tmp/a%25b");
+    }
+
+    #[test]
+    fn finds_trash_directories() {
+        assert!(in_trash(Path::new("/mnt/usb/.Trash-1000/files/.venv")));
+        assert!(in_trash(Path::new("/mnt/usb/.Trash/1000/files/.venv")));
+        assert!(!in_trash(Path::new("/home/me/src/Trash/.venv")));
+        assert!(!in_trash(Path::new("/home/me/src/.Trashy/.venv")));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn copy_tree_copies_read_only_directories() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let from = dir.path().join("from");
+        let read_only = from.join("lib");
+        std::fs::create_dir_all(&read_only).unwrap();
+        std::fs::write(read_only.join("module.py"), "x = 1\n").unwrap();
+        std::os::unix::fs::symlink("module.py", read_only.join("link.py")).unwrap();
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+
+        let to = dir.path().join("to");
+        let copied = copy_tree(&from, &to);
+        // put the permissions back first, so the temporary directory can be cleaned up
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o755)).unwrap();
+        copied.unwrap();
+        let copied_dir = to.join("lib");
+        let mode = std::fs::metadata(&copied_dir).unwrap().permissions().mode();
+        std::fs::set_permissions(&copied_dir, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(mode & 0o777, 0o555);
+        assert_eq!(
+            std::fs::read_to_string(copied_dir.join("module.py")).unwrap(),
+            "x = 1\n"
+        );
+        assert_eq!(
+            std::fs::read_link(copied_dir.join("link.py")).unwrap(),
+            Path::new("module.py")
+        );
+    }
+}
@@ -0,0 +1,35 @@
+//! virtualenvwrapper keeps every environment in `$WORKON_HOME`, or `~/.virtualenvs`, under the
+//! name given to `mkvirtualenv`. Environments made with `mkproject` or `setvirtualenvproject` have
+//! a `.project` file saying where their project is; the rest aren't tied to a project at all.
+
+use std::path::{Path, PathBuf};
+
+use crate::env_var;
+use crate::pipenv;
+use crate::pyvenv::{environments_for, orphaned_in};
+
+/// Where virtualenvwrapper keeps environments
+pub fn workon_home() -> Option<PathBuf> {
+    if let Some(workon_home) = env_var("WORKON_HOME") {
+        return Some(workon_home);
+    }
+    Some(PathBuf::from(std::env::var_os("HOME")?).join(".virtualenvs"))
+}
+
+/// the environments in [workon_home] that belong to `project`
+pub fn locate(project: &Path) -> Vec<PathBuf> {
+    workon_home()
+        .map(|workon_home| environments_for(&workon_home, project))
+        .unwrap_or_default()
+}
+
+/// the environments in [workon_home] whose project doesn't exist any more, with where the project
+/// was. Pipenv uses `$WORKON_HOME` too when it's set, and its environments are left to it.
+pub fn orphaned_environments() -> Vec<(PathBuf, PathBuf)> {
+    workon_home()
+        .map(|workon_home| orphaned_in(&workon_home))
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|(environment, project)| !pipenv::made_for(environment, project))
+        .collect()
+}